@@ -53,7 +53,9 @@ fn main() {
                 let (x, y) = (pos[0] as f32, pos[1] as f32);
 
                 if let Some(p) = last_pos {
-                    canvas.with_image_mut(|canvas| {
+                    let (x0, y0) = (p[0].min(pos[0]) as u32, p[1].min(pos[1]) as u32);
+                    let (x1, y1) = (p[0].max(pos[0]) as u32, p[1].max(pos[1]) as u32);
+                    canvas.with_region_mut([x0, y0, x1 - x0 + 1, y1 - y0 + 1], |canvas| {
                         let (last_x, last_y) = (p[0] as f32, p[1] as f32);
                         let distance = vec2_len(vec2_sub(p, pos)) as u32;
 
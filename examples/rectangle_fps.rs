@@ -32,6 +32,7 @@ fn main() {
         window.draw_2d(&e, |c, g, _| {
             if graphics_tree.is_empty() {
                 draw(n, &c, graphics_tree);
+                graphics_tree.optimize();
             }
 
             graphics_tree.draw(texture_buffer, g);
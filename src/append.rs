@@ -0,0 +1,63 @@
+//! Appending graphics trees recorded separately.
+
+use graphics::DrawState;
+use range::Range;
+
+use bounds::Bounds;
+use GraphicsTree;
+
+impl GraphicsTree {
+    /// Appends the commands of another tree, drawn after the commands of this tree.
+    ///
+    /// The data of the other tree is moved to the end of the buffers of this tree.
+    /// The state is reset before the other commands, which were recorded
+    /// from the default state, and recording continues from the state
+    /// of the other tree. Children are moved along,
+    /// so segment handles of the other tree are no longer valid.
+    ///
+    /// Trees can be recorded on separate threads and appended in order.
+    pub fn append(&mut self, other: GraphicsTree) {
+        use Command::*;
+
+        let GraphicsTree {
+            commands,
+            vertices,
+            uvs,
+            colors,
+            current_color,
+            current_draw_state,
+            children,
+            primitives,
+            dirty_region,
+            ..
+        } = other;
+        let rebase = |range: Range, offset: usize| Range::new(range.offset + offset, range.length);
+        let (v, uv, c) = (self.vertices.len(), self.uvs.len(), self.colors.len());
+        let child = self.children.len();
+        let primitive = self.primitives.len();
+        self.vertices.extend(vertices);
+        self.uvs.extend(uvs);
+        self.colors.extend(colors);
+        self.children.extend(children);
+        self.primitives.extend(primitives);
+        self.dirty_region = Bounds::union_opt(self.dirty_region, dirty_region);
+
+        self.change_state(&[0.0; 4], &DrawState::default());
+        for command in commands {
+            let command = match command {
+                Colored(vr) => Colored(rebase(vr, v)),
+                Colors(vr, cr) => Colors(rebase(vr, v), rebase(cr, c)),
+                Textured(texture, vr, uvr) => Textured(texture, rebase(vr, v), rebase(uvr, uv)),
+                TexturedColor(texture, vr, uvr, cr) => {
+                    TexturedColor(texture, rebase(vr, v), rebase(uvr, uv), rebase(cr, c))
+                }
+                Child(index) => Child(index + child),
+                Primitive(index) => Primitive(index + primitive),
+                command => command,
+            };
+            self.push_command(command);
+        }
+        self.current_color = current_color;
+        self.current_draw_state = current_draw_state;
+    }
+}
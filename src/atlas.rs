@@ -0,0 +1,268 @@
+//! Packing of small textures into shared pages.
+
+use std::collections::HashMap;
+use std::sync::{RwLock, Weak};
+
+use graphics::types::Color;
+use image::RgbaImage;
+use texture::{Filter, TextureSettings, Wrap};
+
+use TextureInner;
+
+/// Packs small images into pages, such that draws can share a backend texture.
+pub(crate) struct Atlas<T> {
+    page_size: u32,
+    max_size: u32,
+    pub pages: Vec<Page<T>>,
+    slots: HashMap<u64, Slot>,
+}
+
+/// A page of the atlas with its backend texture.
+pub(crate) struct Page<T> {
+    pub image: RgbaImage,
+    pub settings: TextureSettings,
+    pub texture: Option<T>,
+    /// Whether the image changed since the backend texture was uploaded.
+    pub dirty: bool,
+    /// Rows of packed images as `[y, height, x]`, where `x` is the free space.
+    shelves: Vec<[u32; 3]>,
+    used: usize,
+}
+
+/// The place of an image in the atlas.
+struct Slot {
+    page: usize,
+    rect: [u32; 4],
+    handle: Weak<RwLock<TextureInner>>,
+    last_used: u64,
+}
+
+impl<T> Atlas<T> {
+    pub fn new(page_size: u32, max_size: u32) -> Atlas<T> {
+        Atlas {
+            page_size,
+            max_size: max_size.min(page_size.saturating_sub(2)),
+            pages: vec![],
+            slots: HashMap::new(),
+        }
+    }
+
+    pub fn page_size(&self) -> u32 {
+        self.page_size
+    }
+
+    /// Returns the page and rectangle `[x, y, w, h]` in pixels of an image.
+    pub fn slot(&self, id: u64) -> Option<(usize, [u32; 4])> {
+        self.slots.get(&id).map(|slot| (slot.page, slot.rect))
+    }
+
+    /// Returns the page and rectangle of an image drawn at a tick.
+    pub fn use_slot(&mut self, id: u64, tick: u64) -> Option<(usize, [u32; 4])> {
+        let slot = self.slots.get_mut(&id)?;
+        slot.last_used = tick;
+        Some((slot.page, slot.rect))
+    }
+
+    /// Returns `true` if an image can be packed into the atlas.
+    ///
+    /// Textures that repeat or use mipmaps are not packed,
+    /// since their neighbours in the page would be sampled.
+    pub fn accepts(&self, inner: &TextureInner) -> bool {
+        let (w, h) = inner.image.dimensions();
+        let settings = &inner.settings;
+        w > 0 && h > 0 && w <= self.max_size && h <= self.max_size &&
+        settings.get_wrap_u() == Wrap::ClampToEdge &&
+        settings.get_wrap_v() == Wrap::ClampToEdge &&
+        !settings.get_generate_mipmap()
+    }
+
+    /// Stores an image drawn at a tick in the atlas, reusing its slot when possible.
+    ///
+    /// Returns the page and rectangle of the image, or `None` when there is no room left,
+    /// and the memory in bytes of a backend texture released by moving the image.
+    pub fn place(
+        &mut self,
+        id: u64,
+        inner: &TextureInner,
+        handle: Weak<RwLock<TextureInner>>,
+        tick: u64
+    ) -> (Option<(usize, [u32; 4])>, usize) {
+        let (w, h) = inner.image.dimensions();
+        let reuse = self.slots.get(&id).is_some_and(|slot| {
+            slot.rect[2] == w && slot.rect[3] == h &&
+            same_settings(&self.pages[slot.page].settings, &inner.settings)
+        });
+        let mut memory = 0;
+        if !reuse {
+            memory = self.free(id);
+            let (page, x, y) = match self.pack(w + 2, h + 2, &inner.settings) {
+                Some(place) => place,
+                None => return (None, memory),
+            };
+            self.pages[page].used += 1;
+            self.slots.insert(id, Slot {page, rect: [x + 1, y + 1, w, h], handle, last_used: tick});
+        }
+        let slot = self.slots.get_mut(&id).unwrap();
+        slot.last_used = tick;
+        let page = &mut self.pages[slot.page];
+        copy_extruded(&inner.image, &mut page.image, slot.rect);
+        page.dirty = true;
+        (Some((slot.page, slot.rect)), memory)
+    }
+
+    /// Removes an image from the atlas,
+    /// releasing the page when all its images are removed.
+    ///
+    /// Returns the memory in bytes of the released backend texture.
+    pub fn free(&mut self, id: u64) -> usize {
+        let page = match self.remove(id) {Some(page) => page, None => return 0};
+        let page = &mut self.pages[page];
+        if page.used > 0 {return 0}
+        page.image = RgbaImage::new(0, 0);
+        page.dirty = true;
+        match page.texture.take() {
+            Some(_) => self.page_size as usize * self.page_size as usize * 4,
+            None => 0,
+        }
+    }
+
+    /// Removes images whose `Texture` handles were all dropped.
+    ///
+    /// Returns the number of removed images
+    /// and the memory in bytes of released backend textures.
+    pub fn collect_garbage(&mut self) -> (usize, usize) {
+        let (handles, memory) = self.free_where(|slot| slot.handle.strong_count() == 0);
+        (handles.len(), memory)
+    }
+
+    /// Removes images that were not drawn since a tick.
+    ///
+    /// Returns the handles of removed images
+    /// and the memory in bytes of released backend textures.
+    pub fn sweep(&mut self, tick: u64) -> (Vec<Weak<RwLock<TextureInner>>>, usize) {
+        self.free_where(|slot| slot.last_used < tick)
+    }
+
+    /// Removes images matching a predicate,
+    /// returning their handles and the memory of released backend textures.
+    fn free_where<P>(&mut self, mut predicate: P) -> (Vec<Weak<RwLock<TextureInner>>>, usize)
+        where P: FnMut(&Slot) -> bool
+    {
+        let ids: Vec<u64> = self.slots.iter()
+            .filter(|&(_, slot)| predicate(slot))
+            .map(|(&id, _)| id)
+            .collect();
+        let handles = ids.iter().map(|id| self.slots[id].handle.clone()).collect();
+        let memory = ids.iter().map(|&id| self.free(id)).sum();
+        (handles, memory)
+    }
+
+    /// Removes an image from its page, returning the page.
+    ///
+    /// The space of a page is reused when all its images are removed.
+    fn remove(&mut self, id: u64) -> Option<usize> {
+        let slot = self.slots.remove(&id)?;
+        let page = &mut self.pages[slot.page];
+        page.used -= 1;
+        if page.used == 0 {page.shelves.clear()}
+        Some(slot.page)
+    }
+
+    /// Finds room for a rectangle in a page with compatible settings,
+    /// adding a new page when needed.
+    fn pack(&mut self, w: u32, h: u32, settings: &TextureSettings) -> Option<(usize, u32, u32)> {
+        let size = self.page_size;
+        for (index, page) in self.pages.iter_mut().enumerate() {
+            if !same_settings(&page.settings, settings) {continue}
+            if let Some((x, y)) = page.pack(w, h, size) {
+                // Released pages get a new image when used again.
+                if page.image.width() == 0 {page.image = RgbaImage::new(size, size)}
+                return Some((index, x, y));
+            }
+        }
+        let mut page = Page {
+            image: RgbaImage::new(size, size),
+            settings: *settings,
+            texture: None,
+            dirty: true,
+            shelves: vec![],
+            used: 0,
+        };
+        let (x, y) = page.pack(w, h, size)?;
+        self.pages.push(page);
+        Some((self.pages.len() - 1, x, y))
+    }
+}
+
+impl<T> Page<T> {
+    /// Packs a rectangle into the first shelf with room, or a new shelf.
+    fn pack(&mut self, w: u32, h: u32, size: u32) -> Option<(u32, u32)> {
+        for shelf in &mut self.shelves {
+            if h <= shelf[1] && shelf[2] + w <= size {
+                let x = shelf[2];
+                shelf[2] += w;
+                return Some((x, shelf[0]));
+            }
+        }
+        let y = self.shelves.last().map_or(0, |shelf| shelf[0] + shelf[1]);
+        if y + h > size || w > size {return None}
+        self.shelves.push([y, h, w]);
+        Some((0, y))
+    }
+}
+
+/// Vertices, uvs and colors of consecutive draws on the same page.
+pub(crate) struct Batch {
+    page_size: f32,
+    pub vertices: Vec<[f32; 2]>,
+    pub uvs: Vec<[f32; 2]>,
+    pub colors: Vec<Color>,
+}
+
+impl Batch {
+    pub fn new(page_size: u32) -> Batch {
+        Batch {page_size: page_size as f32, vertices: vec![], uvs: vec![], colors: vec![]}
+    }
+
+    /// Adds a draw, mapping uvs of the image to uvs of the page.
+    ///
+    /// The uvs must be inside the image, see `inside_image`.
+    pub fn push(&mut self, vertices: &[[f32; 2]], uvs: &[[f32; 2]], rect: [u32; 4]) {
+        let s = self.page_size;
+        self.vertices.extend_from_slice(vertices);
+        self.uvs.extend(uvs.iter().map(|uv| [
+            (rect[0] as f32 + uv[0] * rect[2] as f32) / s,
+            (rect[1] as f32 + uv[1] * rect[3] as f32) / s,
+        ]));
+    }
+}
+
+/// Returns `true` if all uvs are inside `[0, 1]`,
+/// such that a draw only samples its image when packed into a page.
+pub(crate) fn inside_image(uvs: &[[f32; 2]]) -> bool {
+    uvs.iter().all(|uv| (0.0..=1.0).contains(&uv[0]) && (0.0..=1.0).contains(&uv[1]))
+}
+
+/// Copies an image into a rectangle of a page,
+/// repeating the edge pixels around it to avoid bleeding when filtering.
+fn copy_extruded(image: &RgbaImage, page: &mut RgbaImage, rect: [u32; 4]) {
+    let (w, h) = (rect[2] as i64, rect[3] as i64);
+    for y in -1..h + 1 {
+        for x in -1..w + 1 {
+            let pixel = *image.get_pixel(x.clamp(0, w - 1) as u32, y.clamp(0, h - 1) as u32);
+            page.put_pixel((rect[0] as i64 + x) as u32, (rect[1] as i64 + y) as u32, pixel);
+        }
+    }
+}
+
+/// Returns `true` if textures with these settings can share a page.
+fn same_settings(a: &TextureSettings, b: &TextureSettings) -> bool {
+    same_filter(a.get_min(), b.get_min()) &&
+    same_filter(a.get_mag(), b.get_mag()) &&
+    a.get_convert_gamma() == b.get_convert_gamma() &&
+    a.get_compress() == b.get_compress()
+}
+
+fn same_filter(a: Filter, b: Filter) -> bool {
+    matches!((a, b), (Filter::Linear, Filter::Linear) | (Filter::Nearest, Filter::Nearest))
+}
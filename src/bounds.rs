@@ -0,0 +1,167 @@
+//! Axis aligned bounding boxes of recorded geometry.
+
+use std::mem;
+
+use graphics::types::Matrix2d;
+
+use node::transform_vertex;
+use {Command, GraphicsTree};
+
+/// The maximum number of grid cells along each axis.
+const MAX_CELLS: usize = 64;
+
+/// An axis aligned bounding box in normalized device coordinates.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct Bounds {
+    pub min: [f32; 2],
+    pub max: [f32; 2],
+}
+
+impl Bounds {
+    /// Computes the bounds of vertices, or `None` if there are none.
+    pub fn from_vertices(vertices: &[[f32; 2]]) -> Option<Bounds> {
+        let (first, rest) = vertices.split_first()?;
+        let mut res = Bounds {min: *first, max: *first};
+        for v in rest {
+            res.min = [res.min[0].min(v[0]), res.min[1].min(v[1])];
+            res.max = [res.max[0].max(v[0]), res.max[1].max(v[1])];
+        }
+        Some(res)
+    }
+
+    /// Creates bounds from a rectangle `[x, y, w, h]`.
+    pub fn from_rect(rect: [f32; 4]) -> Bounds {
+        Bounds {min: [rect[0], rect[1]], max: [rect[0] + rect[2], rect[1] + rect[3]]}
+    }
+
+    /// Returns the rectangle `[x, y, w, h]`.
+    pub fn to_rect(self) -> [f32; 4] {
+        [self.min[0], self.min[1], self.max[0] - self.min[0], self.max[1] - self.min[1]]
+    }
+
+    /// Returns the smallest bounds containing both.
+    pub fn union(self, other: Bounds) -> Bounds {
+        Bounds {
+            min: [self.min[0].min(other.min[0]), self.min[1].min(other.min[1])],
+            max: [self.max[0].max(other.max[0]), self.max[1].max(other.max[1])],
+        }
+    }
+
+    /// Returns `true` if the other bounds lie inside these bounds.
+    pub fn contains(self, other: Bounds) -> bool {
+        self.min[0] <= other.min[0] && other.max[0] <= self.max[0] &&
+        self.min[1] <= other.min[1] && other.max[1] <= self.max[1]
+    }
+
+    /// Returns `true` if the bounds overlap or touch.
+    pub fn intersects(self, other: Bounds) -> bool {
+        self.min[0] <= other.max[0] && other.min[0] <= self.max[0] &&
+        self.min[1] <= other.max[1] && other.min[1] <= self.max[1]
+    }
+
+    /// Returns the smallest bounds containing both, when either is optional.
+    pub fn union_opt(a: Option<Bounds>, b: Option<Bounds>) -> Option<Bounds> {
+        match (a, b) {
+            (Some(a), Some(b)) => Some(a.union(b)),
+            (a, None) => a,
+            (None, b) => b,
+        }
+    }
+
+    /// Returns the number of cells along each axis
+    /// of a grid indexing `n` bounding boxes.
+    pub fn grid_size(n: usize) -> usize {
+        ((n as f64).sqrt().ceil() as usize).clamp(1, MAX_CELLS)
+    }
+
+    /// Returns the cell `[x, y]` containing a point,
+    /// in a grid of `size` cells along each axis over these bounds.
+    ///
+    /// Points outside the bounds are clamped to the grid.
+    pub fn cell(self, size: usize, point: [f32; 2]) -> [usize; 2] {
+        let axis = |i: usize| {
+            let extent = self.max[i] - self.min[i];
+            if extent <= 0.0 {return 0}
+            let t = (point[i] - self.min[i]) / extent;
+            ((t * size as f32).floor().max(0.0) as usize).min(size - 1)
+        };
+        [axis(0), axis(1)]
+    }
+
+    /// Returns the indices `y * size + x` of the cells overlapping other bounds,
+    /// in a grid of `size` cells along each axis over these bounds.
+    pub fn cells(self, size: usize, other: Bounds) -> impl Iterator<Item = usize> {
+        let [x0, y0] = self.cell(size, other.min);
+        let [x1, y1] = self.cell(size, other.max);
+        (y0..=y1).flat_map(move |y| (x0..=x1).map(move |x| y * size + x))
+    }
+
+    /// Returns the bounds of the transformed corners.
+    pub fn transform(self, m: Matrix2d) -> Bounds {
+        let corners = [
+            transform_vertex(m, self.min),
+            transform_vertex(m, [self.max[0], self.min[1]]),
+            transform_vertex(m, self.max),
+            transform_vertex(m, [self.min[0], self.max[1]]),
+        ];
+        Bounds::from_vertices(&corners).unwrap()
+    }
+}
+
+impl GraphicsTree {
+    /// Appends a command, caching the bounds of its vertices.
+    ///
+    /// All commands are pushed through this method,
+    /// such that the cache has one entry per command.
+    pub(crate) fn push_command(&mut self, command: Command) {
+        let bounds = self.vertex_bounds(&command);
+        self.push_run(self.commands.len(), &command);
+        self.commands.push(command);
+        self.cached_bounds.push(bounds);
+        self.hit_grid.take();
+    }
+
+    /// Removes all commands to push them again, clearing cached data.
+    ///
+    /// The triangles of each primitive are kept, since primitives do not change.
+    pub(crate) fn take_commands(&mut self) -> Vec<Command> {
+        self.cached_bounds.clear();
+        self.hit_grid.take();
+        self.runs.clear();
+        mem::take(&mut self.commands)
+    }
+
+    /// Computes the bounds of the vertices of a draw command.
+    ///
+    /// The bounds of primitives are computed from their parameters
+    /// and may be larger than their triangles.
+    pub(crate) fn vertex_bounds(&self, command: &Command) -> Option<Bounds> {
+        if let Command::Primitive(index) = *command {
+            return self.primitives[index].bounds();
+        }
+        command.vertex_range()
+            .and_then(|range| Bounds::from_vertices(&self.vertices[range.iter()]))
+    }
+
+    /// Returns the bounds of the command at an index, including visible children.
+    pub(crate) fn command_bounds(&self, index: usize) -> Option<Bounds> {
+        match self.commands[index] {
+            Command::Child(child) => self.child_bounds(child),
+            _ => self.cached_bounds[index],
+        }
+    }
+
+    /// Computes the bounds of a child in the coordinates of this tree,
+    /// or `None` if it is not visible.
+    pub(crate) fn child_bounds(&self, index: usize) -> Option<Bounds> {
+        let node = &self.children[index];
+        if !node.visible {return None}
+        node.tree.bounds().map(|b| b.transform(node.transform))
+    }
+
+    /// Computes the bounds of all recorded geometry.
+    pub(crate) fn bounds(&self) -> Option<Bounds> {
+        (0..self.commands.len())
+            .fold(None, |acc, index| Bounds::union_opt(acc, self.command_bounds(index)))
+    }
+}
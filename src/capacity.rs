@@ -0,0 +1,148 @@
+//! Capacity controls and double buffering of graphics trees.
+
+use std::mem;
+use std::sync::OnceLock;
+
+use graphics::types::Color;
+
+use bounds::Bounds;
+use hit::Grid;
+use primitive::Run;
+use {Command, GraphicsTree, Node, Primitive, PrimitiveKind};
+
+/// Lengths of the buffers of a graphics tree.
+///
+/// Used to preallocate memory, see `GraphicsTree::with_capacity`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Capacity {
+    /// The number of commands.
+    pub commands: usize,
+    /// The number of vertices.
+    pub vertices: usize,
+    /// The number of texture coordinates.
+    pub uvs: usize,
+    /// The number of vertex colors.
+    pub colors: usize,
+}
+
+impl GraphicsTree {
+    /// Creates a new graphics tree with preallocated buffers.
+    pub fn with_capacity(capacity: Capacity) -> GraphicsTree {
+        let mut tree = GraphicsTree::new();
+        tree.reserve(capacity);
+        tree
+    }
+
+    /// Reserves room for at least this many more items in each buffer.
+    pub fn reserve(&mut self, additional: Capacity) {
+        self.commands.reserve(additional.commands);
+        self.cached_bounds.reserve(additional.commands);
+        self.vertices.reserve(additional.vertices);
+        self.uvs.reserve(additional.uvs);
+        self.colors.reserve(additional.colors);
+    }
+
+    /// Returns the allocated capacity of each buffer.
+    pub fn capacity(&self) -> Capacity {
+        Capacity {
+            commands: self.commands.capacity(),
+            vertices: self.vertices.capacity(),
+            uvs: self.uvs.capacity(),
+            colors: self.colors.capacity(),
+        }
+    }
+
+    /// Shrinks the buffers to fit their contents, including children.
+    pub fn shrink_to_fit(&mut self) {
+        self.commands.shrink_to_fit();
+        self.cached_bounds.shrink_to_fit();
+        self.vertices.shrink_to_fit();
+        self.uvs.shrink_to_fit();
+        self.colors.shrink_to_fit();
+        self.children.shrink_to_fit();
+        self.primitives.shrink_to_fit();
+        for node in &mut self.children {
+            node.tree.shrink_to_fit();
+        }
+    }
+
+    /// Returns the memory in bytes allocated by the buffers, including children
+    /// and the triangles and hit testing grid cached when drawing or testing.
+    ///
+    /// Images of textures are not included, since they are shared.
+    pub fn memory_usage(&self) -> usize {
+        self.commands.capacity() * mem::size_of::<Command>() +
+        self.cached_bounds.capacity() * mem::size_of::<Option<Bounds>>() +
+        self.vertices.capacity() * mem::size_of::<[f32; 2]>() +
+        self.uvs.capacity() * mem::size_of::<[f32; 2]>() +
+        self.colors.capacity() * mem::size_of::<Color>() +
+        self.children.capacity() * mem::size_of::<Node>() +
+        self.primitives.capacity() * mem::size_of::<Primitive>() +
+        self.primitives.iter().map(|primitive| match primitive.kind {
+            PrimitiveKind::Polygon(_, ref points) => points.capacity() * mem::size_of::<[f64; 2]>(),
+            _ => 0,
+        }).sum::<usize>() +
+        self.tessellation.capacity() * mem::size_of::<OnceLock<GraphicsTree>>() +
+        self.tessellation.iter()
+            .filter_map(OnceLock::get)
+            .map(GraphicsTree::memory_usage)
+            .sum::<usize>() +
+        self.runs.capacity() * mem::size_of::<Run>() +
+        self.runs.iter().map(Run::memory_usage).sum::<usize>() +
+        self.hit_grid.get().map_or(0, Grid::memory_usage) +
+        self.children.iter()
+            .map(|node| node.name().len() + node.tree.memory_usage())
+            .sum::<usize>()
+    }
+}
+
+/// Two graphics trees, one to draw and one to record the next frame.
+///
+/// Swapping clears the tree to record while keeping its memory,
+/// so recording does not reallocate once the buffers are large enough.
+#[derive(Debug, Default)]
+pub struct DoubleBuffer {
+    front: GraphicsTree,
+    back: GraphicsTree,
+}
+
+impl DoubleBuffer {
+    /// Creates a new double buffer with empty trees.
+    pub fn new() -> DoubleBuffer {
+        DoubleBuffer::default()
+    }
+
+    /// Creates a new double buffer with preallocated trees.
+    pub fn with_capacity(capacity: Capacity) -> DoubleBuffer {
+        DoubleBuffer {
+            front: GraphicsTree::with_capacity(capacity),
+            back: GraphicsTree::with_capacity(capacity),
+        }
+    }
+
+    /// Returns the tree to draw.
+    pub fn front(&self) -> &GraphicsTree {
+        &self.front
+    }
+
+    /// Returns the tree to record.
+    pub fn back_mut(&mut self) -> &mut GraphicsTree {
+        &mut self.back
+    }
+
+    /// Returns the tree to draw and the tree to record at the same time,
+    /// for example to record on another thread while drawing.
+    pub fn split(&mut self) -> (&GraphicsTree, &mut GraphicsTree) {
+        (&self.front, &mut self.back)
+    }
+
+    /// Makes the recorded tree the tree to draw,
+    /// then clears the other tree for recording.
+    ///
+    /// Recording settings, such as `GraphicsTree::set_semantic`, are kept.
+    pub fn swap(&mut self) {
+        mem::swap(&mut self.front, &mut self.back);
+        self.back.clear();
+        self.back.semantic = self.front.semantic;
+    }
+}
@@ -0,0 +1,18 @@
+//! Splitting of draw commands into backend calls.
+
+use std::ops::Range;
+
+use graphics::BACK_END_MAX_VERTEX_COUNT;
+
+/// The largest number of whole triangles passed to the backend at once.
+const CHUNK_SIZE: usize = BACK_END_MAX_VERTEX_COUNT - BACK_END_MAX_VERTEX_COUNT % 3;
+
+/// Splits `length` vertices into chunks passed to the backend.
+///
+/// Each chunk holds whole triangles and at most `BACK_END_MAX_VERTEX_COUNT`
+/// vertices, as required by the `Graphics` trait.
+/// Trailing vertices that do not form a triangle are left out.
+pub(crate) fn chunks(length: usize) -> impl Iterator<Item = Range<usize>> {
+    let length = length - length % 3;
+    (0..length).step_by(CHUNK_SIZE).map(move |start| start..length.min(start + CHUNK_SIZE))
+}
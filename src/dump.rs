@@ -0,0 +1,775 @@
+//! Saving and loading of recorded commands.
+//!
+//! A `FrameDump` stores the commands and buffers of a graphics tree,
+//! with texture images stored once per distinct image.
+//! It can be written to a compact binary format with `to_bytes`,
+//! or to any `serde` format when the `serde` feature is enabled.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::sync::{Arc, PoisonError};
+
+use graphics::DrawState;
+use graphics::draw_state::{Blend, Stencil};
+use graphics::types::Color;
+use image::RgbaImage;
+use range::Range;
+use texture::{Filter, TextureSettings, Wrap};
+
+use {Command, GraphicsTree, Texture};
+
+const MAGIC: &[u8; 8] = b"GFXTREE\0";
+const VERSION: u32 = 2;
+
+/// Stores recorded commands in a form that can be saved and loaded.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct FrameDump {
+    /// The textures used by commands, one per distinct image.
+    pub textures: Vec<TextureData>,
+    /// The commands in draw order.
+    pub commands: Vec<CommandData>,
+    /// The vertex positions.
+    pub vertices: Vec<[f32; 2]>,
+    /// The texture coordinates.
+    pub uvs: Vec<[f32; 2]>,
+    /// The vertex colors.
+    pub colors: Vec<[f32; 4]>,
+}
+
+/// Stores the image and settings of a texture.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct TextureData {
+    /// The content hash of the image, which identifies the texture.
+    ///
+    /// When different images have the same hash,
+    /// the next unused value is used instead.
+    pub hash: u64,
+    /// The width of the image.
+    pub width: u32,
+    /// The height of the image.
+    pub height: u32,
+    /// The RGBA pixels of the image, in row order.
+    pub pixels: Vec<u8>,
+    /// The settings used when creating the backend texture.
+    pub settings: TextureSettingsData,
+}
+
+/// Stores the settings of a texture.
+///
+/// The mipmap filter is not stored,
+/// since `TextureSettings` can not change it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct TextureSettingsData {
+    /// Whether colors are converted from sRGB.
+    pub convert_gamma: bool,
+    /// Whether the texture is compressed.
+    pub compress: bool,
+    /// Whether mipmaps are generated.
+    pub generate_mipmap: bool,
+    /// The filter used when minifying.
+    pub min: FilterData,
+    /// The filter used when magnifying.
+    pub mag: FilterData,
+    /// The wrap mode of the horizontal texture coordinate.
+    pub wrap_u: WrapData,
+    /// The wrap mode of the vertical texture coordinate.
+    pub wrap_v: WrapData,
+    /// The color outside the texture with `WrapData::ClampToBorder`.
+    pub border_color: [f32; 4],
+}
+
+/// Stores a sampling filter.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub enum FilterData {
+    /// Blend neighbouring texels.
+    Linear,
+    /// Use the nearest texel.
+    Nearest,
+}
+
+/// Stores a wrap mode.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub enum WrapData {
+    /// Repeat the texture.
+    Repeat,
+    /// Repeat the texture, mirroring every other repetition.
+    MirroredRepeat,
+    /// Clamp coordinates to the edge.
+    ClampToEdge,
+    /// Use the border color outside the texture.
+    ClampToBorder,
+}
+
+/// Stores a command.
+///
+/// Draw commands refer to buffers by `[offset, length]`
+/// and to textures by content hash.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub enum CommandData {
+    /// Clears background with a color.
+    ClearColor(Color),
+    /// Clears stencil buffer with a value.
+    ClearStencil(u8),
+    /// Sets the current color.
+    ChangeColor(Color),
+    /// Sets the current draw state.
+    ChangeDrawState(DrawStateData),
+    /// Draws triangles using the current color.
+    Colored {
+        /// The vertex range.
+        vertices: [usize; 2],
+    },
+    /// Draws triangles using individual vertex colors.
+    Colors {
+        /// The vertex range.
+        vertices: [usize; 2],
+        /// The color range.
+        colors: [usize; 2],
+    },
+    /// Draws triangles using the current color and a texture.
+    Textured {
+        /// The content hash of the texture.
+        texture: u64,
+        /// The vertex range.
+        vertices: [usize; 2],
+        /// The texture coordinate range.
+        uvs: [usize; 2],
+    },
+    /// Draws triangles using individual vertex colors and a texture.
+    TexturedColor {
+        /// The content hash of the texture.
+        texture: u64,
+        /// The vertex range.
+        vertices: [usize; 2],
+        /// The texture coordinate range.
+        uvs: [usize; 2],
+        /// The color range.
+        colors: [usize; 2],
+    },
+    /// Starts commands recorded with a tag.
+    PushTag(u64),
+    /// Ends commands recorded with the last tag.
+    PopTag,
+}
+
+/// Stores a draw state.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub struct DrawStateData {
+    /// Scissor rectangle `[x, y, w, h]`.
+    pub scissor: Option<[u32; 4]>,
+    /// Stencil test.
+    pub stencil: Option<StencilData>,
+    /// Blend function.
+    pub blend: Option<BlendData>,
+}
+
+/// Stores a stencil setting.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub enum StencilData {
+    /// Draw to stencil buffer.
+    Clip(u8),
+    /// Draw pixels that have stencil value.
+    Inside(u8),
+    /// Draw pixels that does not have stencil value.
+    Outside(u8),
+    /// Increment stencil buffer.
+    Increment,
+}
+
+/// Stores a blend setting.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(::serde::Serialize, ::serde::Deserialize))]
+pub enum BlendData {
+    /// Alpha blending.
+    Alpha,
+    /// Additive blending.
+    Add,
+    /// Additive blending with alpha channel.
+    Lighter,
+    /// Multiply color components.
+    Multiply,
+    /// Invert colors.
+    Invert,
+}
+
+/// An error when loading recorded commands.
+#[derive(Clone, Debug, PartialEq)]
+pub enum LoadError {
+    /// The data does not start with the expected header.
+    InvalidHeader,
+    /// The format version is not supported.
+    UnsupportedVersion(u32),
+    /// The data ended unexpectedly.
+    UnexpectedEnd,
+    /// An unknown tag was found.
+    InvalidTag(u8),
+    /// The pixel data of a texture does not match its size.
+    InvalidTexture(u64),
+    /// A command refers to a texture that does not exist.
+    MissingTexture(u64),
+    /// A command refers to data outside a buffer,
+    /// or to ranges whose lengths do not match.
+    InvalidRange,
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LoadError::InvalidHeader => write!(f, "Invalid header"),
+            LoadError::UnsupportedVersion(v) => write!(f, "Unsupported version {}", v),
+            LoadError::UnexpectedEnd => write!(f, "Unexpected end of data"),
+            LoadError::InvalidTag(tag) => write!(f, "Invalid tag {}", tag),
+            LoadError::InvalidTexture(hash) => write!(f, "Invalid texture {:016x}", hash),
+            LoadError::MissingTexture(hash) => write!(f, "Missing texture {:016x}", hash),
+            LoadError::InvalidRange => write!(f, "Range is outside buffer or has invalid length"),
+        }
+    }
+}
+
+impl Error for LoadError {}
+
+impl GraphicsTree {
+    /// Stores the recorded commands in a frame dump.
+    ///
+    /// Children are flattened and primitives tessellated,
+    /// see `GraphicsTree::tessellate`.
+    pub fn to_dump(&self) -> FrameDump {
+        use Command::*;
+
+        if !self.children.is_empty() || !self.primitives.is_empty() {
+            return self.tessellate().to_dump();
+        }
+
+        let mut textures: Vec<TextureData> = vec![];
+        // Remember the hash of each texture handle and the index of each
+        // stored hash, such that each image is hashed and stored once.
+        let mut handles: HashMap<*const (), u64> = HashMap::new();
+        let mut stored: HashMap<u64, usize> = HashMap::new();
+        let mut texture_hash = |texture: &Texture| {
+            let ptr = Arc::as_ptr(&texture.0) as *const ();
+            if let Some(&hash) = handles.get(&ptr) {return hash}
+            let inner = texture.0.read().unwrap_or_else(PoisonError::into_inner);
+            let image = &inner.image;
+            let settings = TextureSettingsData::from(inner.settings);
+            let mut hash = content_hash(image);
+            // Pixels are compared, since different images can have the same hash,
+            // and textures with different settings are stored separately.
+            while let Some(&index) = stored.get(&hash) {
+                let data: &TextureData = &textures[index];
+                if data.pixels == *image.as_raw() && (data.width, data.height) == image.dimensions() &&
+                    data.settings == settings
+                {
+                    break;
+                }
+                hash = hash.wrapping_add(1);
+            }
+            stored.entry(hash).or_insert_with(|| {
+                textures.push(TextureData {
+                    hash,
+                    width: image.width(),
+                    height: image.height(),
+                    pixels: image.as_raw().clone(),
+                    settings,
+                });
+                textures.len() - 1
+            });
+            handles.insert(ptr, hash);
+            hash
+        };
+        let r = |range: Range| [range.offset, range.length];
+        let commands = self.commands.iter().map(|command| match *command {
+            ClearColor(color) => CommandData::ClearColor(color),
+            ClearStencil(value) => CommandData::ClearStencil(value),
+            ChangeColor(color) => CommandData::ChangeColor(color),
+            ChangeDrawState(draw_state) => CommandData::ChangeDrawState(draw_state.into()),
+            Colored(v) => CommandData::Colored {vertices: r(v)},
+            Colors(v, c) => CommandData::Colors {vertices: r(v), colors: r(c)},
+            Textured(ref texture, v, uv) => CommandData::Textured {
+                texture: texture_hash(texture),
+                vertices: r(v),
+                uvs: r(uv),
+            },
+            TexturedColor(ref texture, v, uv, c) => CommandData::TexturedColor {
+                texture: texture_hash(texture),
+                vertices: r(v),
+                uvs: r(uv),
+                colors: r(c),
+            },
+            PushTag(tag) => CommandData::PushTag(tag),
+            PopTag => CommandData::PopTag,
+            Child(_) | Primitive(_) => unreachable!("Children are flattened"),
+        }).collect();
+        FrameDump {
+            textures,
+            commands,
+            vertices: self.vertices.clone(),
+            uvs: self.uvs.clone(),
+            colors: self.colors.clone(),
+        }
+    }
+
+    /// Creates a graphics tree from a frame dump.
+    ///
+    /// Commands sharing a texture hash share the same texture.
+    pub fn from_dump(dump: FrameDump) -> Result<GraphicsTree, LoadError> {
+        let mut textures: HashMap<u64, Texture> = HashMap::new();
+        for data in dump.textures {
+            let hash = data.hash;
+            let len = data.width as u64 * data.height as u64 * 4;
+            if data.pixels.len() as u64 != len {return Err(LoadError::InvalidTexture(hash))}
+            let image = RgbaImage::from_raw(data.width, data.height, data.pixels)
+                .ok_or(LoadError::InvalidTexture(hash))?;
+            textures.insert(hash, Texture::with_settings(image, data.settings.into()));
+        }
+        let texture = |hash: u64| {
+            textures.get(&hash).cloned().ok_or(LoadError::MissingTexture(hash))
+        };
+        let check = |range: [usize; 2], len: usize| {
+            match range[0].checked_add(range[1]) {
+                Some(end) if end <= len => Ok(Range::new(range[0], range[1])),
+                _ => Err(LoadError::InvalidRange),
+            }
+        };
+        let (n_v, n_uv, n_c) = (dump.vertices.len(), dump.uvs.len(), dump.colors.len());
+        // Vertex ranges may end with a partial triangle, which is not drawn,
+        // like when recorded, but need one texture coordinate and color per vertex.
+        let vertices = |range: [usize; 2]| check(range, n_v);
+        let per_vertex = |range: [usize; 2], v: Range, len: usize| {
+            if range[1] != v.length {return Err(LoadError::InvalidRange)}
+            check(range, len)
+        };
+
+        let mut tree = GraphicsTree::new();
+        tree.vertices = dump.vertices;
+        tree.uvs = dump.uvs;
+        tree.colors = dump.colors;
+        for command in dump.commands {
+            let command = match command {
+                CommandData::ClearColor(color) => Command::ClearColor(color),
+                CommandData::ClearStencil(value) => Command::ClearStencil(value),
+                CommandData::ChangeColor(color) => {
+                    tree.current_color = color;
+                    Command::ChangeColor(color)
+                }
+                CommandData::ChangeDrawState(draw_state) => {
+                    tree.current_draw_state = draw_state.into();
+                    Command::ChangeDrawState(tree.current_draw_state)
+                }
+                CommandData::Colored {vertices: v} => Command::Colored(vertices(v)?),
+                CommandData::Colors {vertices: v, colors} => {
+                    let v = vertices(v)?;
+                    Command::Colors(v, per_vertex(colors, v, n_c)?)
+                }
+                CommandData::Textured {texture: hash, vertices: v, uvs} => {
+                    let v = vertices(v)?;
+                    Command::Textured(texture(hash)?, v, per_vertex(uvs, v, n_uv)?)
+                }
+                CommandData::TexturedColor {texture: hash, vertices: v, uvs, colors} => {
+                    let v = vertices(v)?;
+                    Command::TexturedColor(
+                        texture(hash)?,
+                        v,
+                        per_vertex(uvs, v, n_uv)?,
+                        per_vertex(colors, v, n_c)?
+                    )
+                }
+                CommandData::PushTag(tag) => Command::PushTag(tag),
+                CommandData::PopTag => Command::PopTag,
+            };
+            tree.push_command(command);
+        }
+        Ok(tree)
+    }
+
+    /// Saves the recorded commands in a compact binary format.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.to_dump().to_bytes()
+    }
+
+    /// Loads recorded commands saved with `to_bytes`.
+    pub fn from_bytes(bytes: &[u8]) -> Result<GraphicsTree, LoadError> {
+        GraphicsTree::from_dump(FrameDump::from_bytes(bytes)?)
+    }
+}
+
+impl FrameDump {
+    /// Encodes the frame dump in a compact binary format.
+    ///
+    /// All numbers are stored in little endian.
+    /// Data saved by earlier versions without texture settings
+    /// is loaded with the default settings.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut w = Writer(MAGIC.to_vec());
+        w.u32(VERSION);
+        w.len(self.textures.len());
+        for texture in &self.textures {
+            w.u64(texture.hash);
+            w.u32(texture.width);
+            w.u32(texture.height);
+            w.len(texture.pixels.len());
+            w.0.extend_from_slice(&texture.pixels);
+            w.settings(&texture.settings);
+        }
+        w.len(self.commands.len());
+        for command in &self.commands {
+            match *command {
+                CommandData::ClearColor(color) => {w.u8(0); w.f32s(&color)}
+                CommandData::ClearStencil(value) => {w.u8(1); w.u8(value)}
+                CommandData::ChangeColor(color) => {w.u8(2); w.f32s(&color)}
+                CommandData::ChangeDrawState(draw_state) => {w.u8(3); w.draw_state(&draw_state)}
+                CommandData::Colored {vertices} => {w.u8(4); w.range(vertices)}
+                CommandData::Colors {vertices, colors} => {
+                    w.u8(5);
+                    w.range(vertices);
+                    w.range(colors);
+                }
+                CommandData::Textured {texture, vertices, uvs} => {
+                    w.u8(6);
+                    w.u64(texture);
+                    w.range(vertices);
+                    w.range(uvs);
+                }
+                CommandData::TexturedColor {texture, vertices, uvs, colors} => {
+                    w.u8(7);
+                    w.u64(texture);
+                    w.range(vertices);
+                    w.range(uvs);
+                    w.range(colors);
+                }
+                CommandData::PushTag(tag) => {w.u8(8); w.u64(tag)}
+                CommandData::PopTag => w.u8(9),
+            }
+        }
+        w.len(self.vertices.len());
+        for v in &self.vertices {w.f32s(v)}
+        w.len(self.uvs.len());
+        for uv in &self.uvs {w.f32s(uv)}
+        w.len(self.colors.len());
+        for c in &self.colors {w.f32s(c)}
+        w.0
+    }
+
+    /// Decodes a frame dump from the binary format.
+    pub fn from_bytes(bytes: &[u8]) -> Result<FrameDump, LoadError> {
+        let mut r = Reader(bytes);
+        if r.take(MAGIC.len())? != MAGIC {return Err(LoadError::InvalidHeader)}
+        let version = r.u32()?;
+        if version != 1 && version != VERSION {return Err(LoadError::UnsupportedVersion(version))}
+        let n = r.len()?;
+        let mut textures = Vec::with_capacity(n.min(r.0.len()));
+        for _ in 0..n {
+            let hash = r.u64()?;
+            let width = r.u32()?;
+            let height = r.u32()?;
+            let len = r.len()?;
+            let pixels = r.take(len)?.to_vec();
+            let settings = match version {
+                1 => TextureSettings::new().into(),
+                _ => r.settings()?,
+            };
+            textures.push(TextureData {hash, width, height, pixels, settings});
+        }
+        let n = r.len()?;
+        let mut commands = Vec::with_capacity(n.min(r.0.len()));
+        for _ in 0..n {
+            commands.push(match r.u8()? {
+                0 => CommandData::ClearColor(r.f32s()?),
+                1 => CommandData::ClearStencil(r.u8()?),
+                2 => CommandData::ChangeColor(r.f32s()?),
+                3 => CommandData::ChangeDrawState(r.draw_state()?),
+                4 => CommandData::Colored {vertices: r.range()?},
+                5 => CommandData::Colors {vertices: r.range()?, colors: r.range()?},
+                6 => CommandData::Textured {
+                    texture: r.u64()?,
+                    vertices: r.range()?,
+                    uvs: r.range()?,
+                },
+                7 => CommandData::TexturedColor {
+                    texture: r.u64()?,
+                    vertices: r.range()?,
+                    uvs: r.range()?,
+                    colors: r.range()?,
+                },
+                8 => CommandData::PushTag(r.u64()?),
+                9 => CommandData::PopTag,
+                tag => return Err(LoadError::InvalidTag(tag)),
+            });
+        }
+        let vertices = r.vec(|r| r.f32s())?;
+        let uvs = r.vec(|r| r.f32s())?;
+        let colors = r.vec(|r| r.f32s())?;
+        Ok(FrameDump {textures, commands, vertices, uvs, colors})
+    }
+}
+
+impl From<DrawState> for DrawStateData {
+    fn from(draw_state: DrawState) -> DrawStateData {
+        DrawStateData {
+            scissor: draw_state.scissor,
+            stencil: draw_state.stencil.map(|stencil| match stencil {
+                Stencil::Clip(v) => StencilData::Clip(v),
+                Stencil::Inside(v) => StencilData::Inside(v),
+                Stencil::Outside(v) => StencilData::Outside(v),
+                Stencil::Increment => StencilData::Increment,
+            }),
+            blend: draw_state.blend.map(|blend| match blend {
+                Blend::Alpha => BlendData::Alpha,
+                Blend::Add => BlendData::Add,
+                Blend::Lighter => BlendData::Lighter,
+                Blend::Multiply => BlendData::Multiply,
+                Blend::Invert => BlendData::Invert,
+            }),
+        }
+    }
+}
+
+impl From<DrawStateData> for DrawState {
+    fn from(data: DrawStateData) -> DrawState {
+        DrawState {
+            scissor: data.scissor,
+            stencil: data.stencil.map(|stencil| match stencil {
+                StencilData::Clip(v) => Stencil::Clip(v),
+                StencilData::Inside(v) => Stencil::Inside(v),
+                StencilData::Outside(v) => Stencil::Outside(v),
+                StencilData::Increment => Stencil::Increment,
+            }),
+            blend: data.blend.map(|blend| match blend {
+                BlendData::Alpha => Blend::Alpha,
+                BlendData::Add => Blend::Add,
+                BlendData::Lighter => Blend::Lighter,
+                BlendData::Multiply => Blend::Multiply,
+                BlendData::Invert => Blend::Invert,
+            }),
+        }
+    }
+}
+
+impl From<TextureSettings> for TextureSettingsData {
+    fn from(settings: TextureSettings) -> TextureSettingsData {
+        let filter = |filter| match filter {
+            Filter::Linear => FilterData::Linear,
+            Filter::Nearest => FilterData::Nearest,
+        };
+        let wrap = |wrap| match wrap {
+            Wrap::Repeat => WrapData::Repeat,
+            Wrap::MirroredRepeat => WrapData::MirroredRepeat,
+            Wrap::ClampToEdge => WrapData::ClampToEdge,
+            Wrap::ClampToBorder => WrapData::ClampToBorder,
+        };
+        TextureSettingsData {
+            convert_gamma: settings.get_convert_gamma(),
+            compress: settings.get_compress(),
+            generate_mipmap: settings.get_generate_mipmap(),
+            min: filter(settings.get_min()),
+            mag: filter(settings.get_mag()),
+            wrap_u: wrap(settings.get_wrap_u()),
+            wrap_v: wrap(settings.get_wrap_v()),
+            border_color: settings.get_border_color(),
+        }
+    }
+}
+
+impl From<TextureSettingsData> for TextureSettings {
+    fn from(data: TextureSettingsData) -> TextureSettings {
+        let filter = |filter| match filter {
+            FilterData::Linear => Filter::Linear,
+            FilterData::Nearest => Filter::Nearest,
+        };
+        let wrap = |wrap| match wrap {
+            WrapData::Repeat => Wrap::Repeat,
+            WrapData::MirroredRepeat => Wrap::MirroredRepeat,
+            WrapData::ClampToEdge => Wrap::ClampToEdge,
+            WrapData::ClampToBorder => Wrap::ClampToBorder,
+        };
+        TextureSettings::new()
+            .convert_gamma(data.convert_gamma)
+            .compress(data.compress)
+            .generate_mipmap(data.generate_mipmap)
+            .min(filter(data.min))
+            .mag(filter(data.mag))
+            .wrap_u(wrap(data.wrap_u))
+            .wrap_v(wrap(data.wrap_v))
+            .border_color(data.border_color)
+    }
+}
+
+/// Computes a 64 bit FNV-1a hash of an image's size and pixels.
+///
+/// This hash is stable across platforms and compiler versions.
+pub fn content_hash(image: &RgbaImage) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    let size = [image.width().to_le_bytes(), image.height().to_le_bytes()];
+    for &byte in size.iter().flatten().chain(image.as_raw()) {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash
+}
+
+struct Writer(Vec<u8>);
+
+impl Writer {
+    fn u8(&mut self, val: u8) {self.0.push(val)}
+    fn u32(&mut self, val: u32) {self.0.extend_from_slice(&val.to_le_bytes())}
+    fn u64(&mut self, val: u64) {self.0.extend_from_slice(&val.to_le_bytes())}
+    fn len(&mut self, val: usize) {self.u64(val as u64)}
+    fn range(&mut self, range: [usize; 2]) {
+        self.len(range[0]);
+        self.len(range[1]);
+    }
+    fn f32s(&mut self, vals: &[f32]) {
+        for val in vals {self.0.extend_from_slice(&val.to_le_bytes())}
+    }
+    fn settings(&mut self, settings: &TextureSettingsData) {
+        let filter = |filter| match filter {
+            FilterData::Linear => 0,
+            FilterData::Nearest => 1,
+        };
+        let wrap = |wrap| match wrap {
+            WrapData::Repeat => 0,
+            WrapData::MirroredRepeat => 1,
+            WrapData::ClampToEdge => 2,
+            WrapData::ClampToBorder => 3,
+        };
+        self.u8(settings.convert_gamma as u8);
+        self.u8(settings.compress as u8);
+        self.u8(settings.generate_mipmap as u8);
+        self.u8(filter(settings.min));
+        self.u8(filter(settings.mag));
+        self.u8(wrap(settings.wrap_u));
+        self.u8(wrap(settings.wrap_v));
+        self.f32s(&settings.border_color);
+    }
+    fn draw_state(&mut self, draw_state: &DrawStateData) {
+        match draw_state.scissor {
+            None => self.u8(0),
+            Some(rect) => {
+                self.u8(1);
+                for &val in &rect {self.u32(val)}
+            }
+        }
+        match draw_state.stencil {
+            None => self.u8(0),
+            Some(StencilData::Clip(v)) => {self.u8(1); self.u8(v)}
+            Some(StencilData::Inside(v)) => {self.u8(2); self.u8(v)}
+            Some(StencilData::Outside(v)) => {self.u8(3); self.u8(v)}
+            Some(StencilData::Increment) => self.u8(4),
+        }
+        self.u8(match draw_state.blend {
+            None => 0,
+            Some(BlendData::Alpha) => 1,
+            Some(BlendData::Add) => 2,
+            Some(BlendData::Lighter) => 3,
+            Some(BlendData::Multiply) => 4,
+            Some(BlendData::Invert) => 5,
+        });
+    }
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], LoadError> {
+        if self.0.len() < n {return Err(LoadError::UnexpectedEnd)}
+        let (head, tail) = self.0.split_at(n);
+        self.0 = tail;
+        Ok(head)
+    }
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], LoadError> {
+        let mut res = [0; N];
+        res.copy_from_slice(self.take(N)?);
+        Ok(res)
+    }
+    fn u8(&mut self) -> Result<u8, LoadError> {Ok(self.take(1)?[0])}
+    fn u32(&mut self) -> Result<u32, LoadError> {Ok(u32::from_le_bytes(self.array()?))}
+    fn u64(&mut self) -> Result<u64, LoadError> {Ok(u64::from_le_bytes(self.array()?))}
+    fn len(&mut self) -> Result<usize, LoadError> {
+        let val = self.u64()?;
+        if val > usize::MAX as u64 {return Err(LoadError::InvalidRange)}
+        Ok(val as usize)
+    }
+    fn range(&mut self) -> Result<[usize; 2], LoadError> {Ok([self.len()?, self.len()?])}
+    fn f32s<const N: usize>(&mut self) -> Result<[f32; N], LoadError> {
+        let mut res = [0.0; N];
+        for val in &mut res {*val = f32::from_le_bytes(self.array()?)}
+        Ok(res)
+    }
+    fn vec<T, F>(&mut self, mut f: F) -> Result<Vec<T>, LoadError>
+        where F: FnMut(&mut Reader<'a>) -> Result<T, LoadError>
+    {
+        let n = self.len()?;
+        // Limit preallocation, since the length is not trusted.
+        let mut res = Vec::with_capacity(n.min(self.0.len()));
+        for _ in 0..n {res.push(f(self)?)}
+        Ok(res)
+    }
+    fn bool(&mut self) -> Result<bool, LoadError> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            tag => Err(LoadError::InvalidTag(tag)),
+        }
+    }
+    fn filter(&mut self) -> Result<FilterData, LoadError> {
+        match self.u8()? {
+            0 => Ok(FilterData::Linear),
+            1 => Ok(FilterData::Nearest),
+            tag => Err(LoadError::InvalidTag(tag)),
+        }
+    }
+    fn wrap(&mut self) -> Result<WrapData, LoadError> {
+        match self.u8()? {
+            0 => Ok(WrapData::Repeat),
+            1 => Ok(WrapData::MirroredRepeat),
+            2 => Ok(WrapData::ClampToEdge),
+            3 => Ok(WrapData::ClampToBorder),
+            tag => Err(LoadError::InvalidTag(tag)),
+        }
+    }
+    fn settings(&mut self) -> Result<TextureSettingsData, LoadError> {
+        Ok(TextureSettingsData {
+            convert_gamma: self.bool()?,
+            compress: self.bool()?,
+            generate_mipmap: self.bool()?,
+            min: self.filter()?,
+            mag: self.filter()?,
+            wrap_u: self.wrap()?,
+            wrap_v: self.wrap()?,
+            border_color: self.f32s()?,
+        })
+    }
+    fn draw_state(&mut self) -> Result<DrawStateData, LoadError> {
+        let scissor = match self.u8()? {
+            0 => None,
+            1 => Some([self.u32()?, self.u32()?, self.u32()?, self.u32()?]),
+            tag => return Err(LoadError::InvalidTag(tag)),
+        };
+        let stencil = match self.u8()? {
+            0 => None,
+            1 => Some(StencilData::Clip(self.u8()?)),
+            2 => Some(StencilData::Inside(self.u8()?)),
+            3 => Some(StencilData::Outside(self.u8()?)),
+            4 => Some(StencilData::Increment),
+            tag => return Err(LoadError::InvalidTag(tag)),
+        };
+        let blend = match self.u8()? {
+            0 => None,
+            1 => Some(BlendData::Alpha),
+            2 => Some(BlendData::Add),
+            3 => Some(BlendData::Lighter),
+            4 => Some(BlendData::Multiply),
+            5 => Some(BlendData::Invert),
+            tag => return Err(LoadError::InvalidTag(tag)),
+        };
+        Ok(DrawStateData {scissor, stencil, blend})
+    }
+}
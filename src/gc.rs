@@ -0,0 +1,109 @@
+//! Collection and eviction of backend textures.
+
+use {Entry, TextureBuffer};
+
+impl<F, T> TextureBuffer<F, T> {
+    /// Returns the number of stored backend textures.
+    pub fn len(&self) -> usize {
+        self.textures.len()
+    }
+
+    /// Returns `true` if no backend textures are stored.
+    pub fn is_empty(&self) -> bool {
+        self.textures.is_empty()
+    }
+
+    /// Returns the estimated memory in bytes used by backend textures.
+    pub fn memory_usage(&self) -> usize {
+        self.memory_usage
+    }
+
+    /// Returns the memory budget in bytes, if any.
+    pub fn memory_budget(&self) -> Option<usize> {
+        self.memory_budget
+    }
+
+    /// Sets the memory budget in bytes.
+    ///
+    /// When a texture is created and the memory usage exceeds the budget,
+    /// the least recently used textures are removed.
+    /// Removed textures are created again the next time they are drawn.
+    pub fn set_memory_budget(&mut self, budget: Option<usize>) {
+        self.memory_budget = budget;
+        self.evict_over_budget(None);
+    }
+
+    /// Removes backend textures whose `Texture` handles were all dropped.
+    ///
+    /// This is called by `GraphicsTree::draw`.
+    /// Returns the number of removed textures.
+    pub fn collect_garbage(&mut self) -> usize {
+        let (atlas, memory) = self.atlas.as_mut().map_or((0, 0), |atlas| atlas.collect_garbage());
+        self.memory_usage -= memory;
+        atlas + self.remove_where(|_, entry| entry.handle.strong_count() == 0)
+    }
+
+    /// Removes backend textures and atlas images that were not drawn since the last sweep.
+    ///
+    /// Call this once per frame to free textures that are kept alive
+    /// but no longer drawn.
+    /// Returns the number of removed textures and atlas images.
+    pub fn sweep_unused(&mut self) -> usize {
+        let sweep_tick = self.sweep_tick;
+        self.sweep_tick = self.tick + 1;
+        let (handles, memory) = self.atlas.as_mut().map_or((vec![], 0), |atlas| atlas.sweep(sweep_tick));
+        self.memory_usage -= memory;
+        // Swept images are placed in the atlas again the next time they are drawn.
+        for handle in &handles {
+            if let Some(handle) = handle.upgrade() {
+                if let Ok(mut inner) = handle.write() {
+                    inner.needs_update = true;
+                    inner.dirty_rect = None;
+                }
+            }
+        }
+        handles.len() + self.remove_where(|_, entry| entry.last_used < sweep_tick)
+    }
+
+    /// Removes least recently used textures until the memory usage
+    /// is within budget, keeping the texture with id `keep`.
+    pub(crate) fn evict_over_budget(&mut self, keep: Option<u64>) {
+        let budget = match self.memory_budget {Some(x) => x, None => return};
+        while self.memory_usage > budget {
+            let lru = self.textures.iter()
+                .filter(|&(id, _)| Some(*id) != keep)
+                .min_by_key(|&(_, entry)| entry.last_used)
+                .map(|(&id, _)| id);
+            match lru {
+                Some(id) => {self.remove_where(|&other, _| other == id);}
+                None => break,
+            }
+        }
+    }
+
+    /// Removes textures matching a predicate, together with their atlas images.
+    ///
+    /// Textures that are still alive get their id reset,
+    /// so they are created again the next time they are drawn.
+    fn remove_where<P>(&mut self, mut predicate: P) -> usize
+        where P: FnMut(&u64, &Entry<T>) -> bool
+    {
+        let ids: Vec<u64> = self.textures.iter()
+            .filter(|&(id, entry)| predicate(id, entry))
+            .map(|(&id, _)| id)
+            .collect();
+        for id in &ids {
+            let entry = self.textures.remove(id).unwrap();
+            self.memory_usage -= entry.memory;
+            if let Some(ref mut atlas) = self.atlas {
+                self.memory_usage -= atlas.free(*id);
+            }
+            if let Some(handle) = entry.handle.upgrade() {
+                if let Ok(mut inner) = handle.write() {
+                    if inner.id == Some(*id) {inner.id = None}
+                }
+            }
+        }
+        ids.len()
+    }
+}
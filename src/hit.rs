@@ -0,0 +1,201 @@
+//! Hit testing of recorded geometry.
+
+use std::mem;
+use std::sync::PoisonError;
+
+use graphics::DrawState;
+use graphics::types::Matrix2d;
+
+use bounds::Bounds;
+use {texel, writes_stencil, Command, GraphicsTree};
+
+/// The tolerance of barycentric coordinates,
+/// such that points on edges shared by triangles are not missed by rounding.
+const EPSILON: f32 = 1e-5;
+
+/// The draw command under a point, see `GraphicsTree::hit_test`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Hit {
+    /// The indices in `GraphicsTree::children` of the children
+    /// containing the command, starting from the tree that was tested.
+    pub path: Vec<usize>,
+    /// The index of the command in `GraphicsTree::commands`
+    /// of the tree containing it.
+    pub command: usize,
+    /// The innermost tag of the command, or of the child containing it,
+    /// see `GraphicsTree::push_tag`.
+    pub tag: Option<u64>,
+}
+
+/// A uniform grid of the triangles drawn by a tree, excluding children.
+#[derive(Clone, Debug)]
+pub(crate) struct Grid {
+    bounds: Option<Bounds>,
+    size: usize,
+    /// The command index and first vertex of triangles overlapping each cell.
+    cells: Vec<Vec<(usize, usize)>>,
+    /// The indices of children and primitives, in draw order.
+    layers: Vec<usize>,
+    /// The innermost tag of each command.
+    tags: Vec<Option<u64>>,
+}
+
+impl Grid {
+    /// Returns the memory in bytes allocated by the grid.
+    pub fn memory_usage(&self) -> usize {
+        self.cells.capacity() * mem::size_of::<Vec<(usize, usize)>>() +
+        self.cells.iter().map(|cell| cell.capacity() * mem::size_of::<(usize, usize)>()).sum::<usize>() +
+        self.layers.capacity() * mem::size_of::<usize>() +
+        self.tags.capacity() * mem::size_of::<Option<u64>>()
+    }
+
+    fn new(tree: &GraphicsTree) -> Grid {
+        use Command::*;
+
+        let mut triangles: Vec<(usize, usize, Bounds)> = vec![];
+        let mut layers = vec![];
+        let mut draw_state: DrawState = Default::default();
+        for (index, command) in tree.commands.iter().enumerate() {
+            match *command {
+                ChangeDrawState(new_draw_state) => draw_state = new_draw_state,
+                Child(_) | Primitive(_) => layers.push(index),
+                // A clear hides everything drawn before it.
+                ClearColor(_) => {
+                    triangles.clear();
+                    layers.clear();
+                }
+                _ => {}
+            }
+            if writes_stencil(&draw_state) {continue}
+            if let Some(range) = command.vertex_range() {
+                for start in (range.offset..range.next_offset() - range.length % 3).step_by(3) {
+                    if let Some(bounds) = Bounds::from_vertices(&tree.vertices[start..start + 3]) {
+                        triangles.push((index, start, bounds));
+                    }
+                }
+            }
+        }
+
+        let bounds = triangles.iter().fold(None, |acc, &(_, _, b)| Bounds::union_opt(acc, Some(b)));
+        let size = Bounds::grid_size(triangles.len());
+        let mut cells = vec![vec![]; size * size];
+        if let Some(bounds) = bounds {
+            for &(index, start, b) in &triangles {
+                for cell in bounds.cells(size, b) {
+                    cells[cell].push((index, start));
+                }
+            }
+        }
+        Grid {bounds, size, cells, layers, tags: tree.command_tags()}
+    }
+}
+
+impl GraphicsTree {
+    /// Returns the topmost draw command whose triangles contain a point,
+    /// in normalized device coordinates.
+    ///
+    /// Commands drawn later are on top, and visible children are tested
+    /// with their transforms. Draws that only write to the stencil buffer
+    /// are ignored, while scissor rectangles and stencil tests are not applied.
+    ///
+    /// The triangles are indexed in a grid, which is built on the first test
+    /// after recording and reused until the commands change.
+    pub fn hit_test(&self, point: [f32; 2]) -> Option<Hit> {
+        self.hit(point, None)
+    }
+
+    /// Like `hit_test`, but ignores pixels of textured draws whose
+    /// texture alpha is less than `min_alpha`.
+    ///
+    /// Textures are sampled from the image stored in the texture,
+    /// using nearest filtering.
+    pub fn hit_test_with_alpha(&self, point: [f32; 2], min_alpha: u8) -> Option<Hit> {
+        self.hit(point, Some(min_alpha))
+    }
+
+    fn hit(&self, point: [f32; 2], min_alpha: Option<u8>) -> Option<Hit> {
+        let grid = self.hit_grid.get_or_init(|| Grid::new(self));
+        let mut top: Option<usize> = None;
+        if let Some(bounds) = grid.bounds {
+            if bounds.contains(Bounds {min: point, max: point}) {
+                let [x, y] = bounds.cell(grid.size, point);
+                for &(index, start) in &grid.cells[y * grid.size + x] {
+                    if top.is_some_and(|top| top >= index) {continue}
+                    if self.triangle_hit(index, start, point, min_alpha) {top = Some(index)}
+                }
+            }
+        }
+        for &index in grid.layers.iter().rev() {
+            if top.is_some_and(|top| top > index) {break}
+            match self.commands[index] {
+                Command::Child(child) => {
+                    let node = &self.children[child];
+                    if !node.visible {continue}
+                    let point = match inverse_transform(node.transform, point) {
+                        Some(point) => point,
+                        None => continue,
+                    };
+                    if let Some(mut hit) = node.tree.hit(point, min_alpha) {
+                        hit.path.insert(0, child);
+                        hit.tag = hit.tag.or(grid.tags[index]);
+                        return Some(hit);
+                    }
+                }
+                _ => {
+                    if self.tessellated(index).hit(point, min_alpha).is_some() {
+                        return Some(Hit {path: vec![], command: index, tag: grid.tags[index]});
+                    }
+                }
+            }
+        }
+        top.map(|command| Hit {path: vec![], command, tag: grid.tags[command]})
+    }
+
+    /// Returns `true` if a triangle of a draw command contains a point.
+    fn triangle_hit(&self, index: usize, start: usize, point: [f32; 2], min_alpha: Option<u8>) -> bool {
+        use Command::*;
+
+        let tri = &self.vertices[start..start + 3];
+        let b = match barycentric(tri, point) {
+            Some(b) => b,
+            None => return false,
+        };
+        let min_alpha = match min_alpha {
+            Some(min_alpha) => min_alpha,
+            None => return true,
+        };
+        let (texture, vertex_range, uv_range) = match self.commands[index] {
+            Textured(ref texture, v, uv) | TexturedColor(ref texture, v, uv, _) => (texture, v, uv),
+            _ => return true,
+        };
+        let uv = uv_range.offset + start - vertex_range.offset;
+        let uvs = &self.uvs[uv..uv + 3];
+        let inner = texture.0.read().unwrap_or_else(PoisonError::into_inner);
+        texel(&inner.image, uvs, b).is_some_and(|pixel| pixel[3] >= min_alpha)
+    }
+}
+
+/// Returns the barycentric coordinates of a point inside a triangle or on its edges,
+/// or `None` if the point is outside or the triangle is degenerate.
+fn barycentric(tri: &[[f32; 2]], p: [f32; 2]) -> Option<[f32; 3]> {
+    let [a, b, c] = [tri[0], tri[1], tri[2]];
+    let det = (b[1] - c[1]) * (a[0] - c[0]) + (c[0] - b[0]) * (a[1] - c[1]);
+    if det == 0.0 {return None}
+    let l0 = ((b[1] - c[1]) * (p[0] - c[0]) + (c[0] - b[0]) * (p[1] - c[1])) / det;
+    let l1 = ((c[1] - a[1]) * (p[0] - c[0]) + (a[0] - c[0]) * (p[1] - c[1])) / det;
+    let l2 = 1.0 - l0 - l1;
+    if l0 < -EPSILON || l1 < -EPSILON || l2 < -EPSILON {return None}
+    Some([l0, l1, l2])
+}
+
+/// Maps a point through the inverse of a transform,
+/// or returns `None` if the transform is not invertible.
+fn inverse_transform(m: Matrix2d, p: [f32; 2]) -> Option<[f32; 2]> {
+    let det = m[0][0] * m[1][1] - m[0][1] * m[1][0];
+    if det == 0.0 {return None}
+    let (x, y) = (p[0] as f64 - m[0][2], p[1] as f64 - m[1][2]);
+    Some([
+        ((m[1][1] * x - m[0][1] * y) / det) as f32,
+        ((m[0][0] * y - m[1][0] * x) / det) as f32,
+    ])
+}
@@ -0,0 +1,227 @@
+//! Inspection of recorded commands, and recording them again.
+
+use std::ptr;
+use std::slice;
+
+use graphics::DrawState;
+use graphics::types::Color;
+use range::Range;
+
+use {Command, GraphicsTree, Node, Primitive, Texture};
+
+/// A read-only view of a recorded command.
+///
+/// Draw commands refer to the vertex, texture coordinate and color data
+/// stored in the graphics tree.
+#[derive(Clone, Copy, Debug)]
+#[non_exhaustive]
+pub enum CommandRef<'a> {
+    /// Clears background with a color.
+    ClearColor(Color),
+    /// Clears stencil buffer with a value.
+    ClearStencil(u8),
+    /// Sets the color used by `Colored` and `Textured`.
+    ChangeColor(Color),
+    /// Sets the draw state used by all draw commands.
+    ChangeDrawState(DrawState),
+    /// Draws triangles using the current color.
+    Colored {
+        /// The vertex positions.
+        vertices: &'a [[f32; 2]],
+    },
+    /// Draws triangles using individual vertex colors.
+    Colors {
+        /// The vertex positions.
+        vertices: &'a [[f32; 2]],
+        /// The vertex colors.
+        colors: &'a [[f32; 4]],
+    },
+    /// Draws triangles using the current color and a texture.
+    Textured {
+        /// The texture.
+        texture: &'a Texture,
+        /// The vertex positions.
+        vertices: &'a [[f32; 2]],
+        /// The texture coordinates.
+        uvs: &'a [[f32; 2]],
+    },
+    /// Draws triangles using individual vertex colors and a texture.
+    TexturedColor {
+        /// The texture.
+        texture: &'a Texture,
+        /// The vertex positions.
+        vertices: &'a [[f32; 2]],
+        /// The texture coordinates.
+        uvs: &'a [[f32; 2]],
+        /// The vertex colors.
+        colors: &'a [[f32; 4]],
+    },
+    /// Draws a named child with its transform, if visible.
+    Child(&'a Node),
+    /// Draws a shape or image, tessellated when drawn.
+    Primitive(&'a Primitive),
+    /// Starts commands recorded with a tag.
+    PushTag(u64),
+    /// Ends commands recorded with the last tag.
+    PopTag,
+}
+
+/// An iterator over the recorded commands of a graphics tree.
+pub struct Commands<'a> {
+    tree: &'a GraphicsTree,
+    iter: slice::Iter<'a, Command>,
+}
+
+impl GraphicsTree {
+    /// Returns an iterator over the recorded commands, in draw order.
+    pub fn commands(&self) -> Commands<'_> {
+        Commands {
+            tree: self,
+            iter: self.commands.iter(),
+        }
+    }
+
+    /// Records a command, copying the data it refers to.
+    ///
+    /// Together with `commands`, this rebuilds a tree with rewritten commands,
+    /// for example in a `Pass`. Children and primitives are cloned.
+    ///
+    /// Panics if texture coordinates or colors do not have one entry per vertex.
+    pub fn push(&mut self, command: CommandRef<'_>) {
+        let command = match command {
+            CommandRef::ClearColor(color) => Command::ClearColor(color),
+            CommandRef::ClearStencil(value) => Command::ClearStencil(value),
+            CommandRef::ChangeColor(color) => {
+                self.current_color = color;
+                Command::ChangeColor(color)
+            }
+            CommandRef::ChangeDrawState(draw_state) => {
+                self.current_draw_state = draw_state;
+                Command::ChangeDrawState(draw_state)
+            }
+            CommandRef::Colored {vertices} => Command::Colored(extend(&mut self.vertices, vertices)),
+            CommandRef::Colors {vertices, colors} => {
+                assert_eq!(vertices.len(), colors.len());
+                Command::Colors(extend(&mut self.vertices, vertices), extend(&mut self.colors, colors))
+            }
+            CommandRef::Textured {texture, vertices, uvs} => {
+                assert_eq!(vertices.len(), uvs.len());
+                Command::Textured(
+                    texture.clone(),
+                    extend(&mut self.vertices, vertices),
+                    extend(&mut self.uvs, uvs)
+                )
+            }
+            CommandRef::TexturedColor {texture, vertices, uvs, colors} => {
+                assert_eq!(vertices.len(), uvs.len());
+                assert_eq!(vertices.len(), colors.len());
+                Command::TexturedColor(
+                    texture.clone(),
+                    extend(&mut self.vertices, vertices),
+                    extend(&mut self.uvs, uvs),
+                    extend(&mut self.colors, colors)
+                )
+            }
+            CommandRef::Child(node) => {
+                self.children.push(node.clone());
+                Command::Child(self.children.len() - 1)
+            }
+            CommandRef::Primitive(primitive) => {
+                self.primitives.push(primitive.clone());
+                Command::Primitive(self.primitives.len() - 1)
+            }
+            CommandRef::PushTag(tag) => Command::PushTag(tag),
+            CommandRef::PopTag => Command::PopTag,
+        };
+        self.push_command(command);
+    }
+
+    /// Resolves the ranges of a command into slices.
+    fn command_ref<'a>(&'a self, command: &'a Command) -> CommandRef<'a> {
+        use Command::*;
+
+        match *command {
+            ClearColor(color) => CommandRef::ClearColor(color),
+            ClearStencil(value) => CommandRef::ClearStencil(value),
+            ChangeColor(color) => CommandRef::ChangeColor(color),
+            ChangeDrawState(draw_state) => CommandRef::ChangeDrawState(draw_state),
+            Colored(vertex_range) => CommandRef::Colored {
+                vertices: &self.vertices[vertex_range.iter()],
+            },
+            Colors(vertex_range, color_range) => CommandRef::Colors {
+                vertices: &self.vertices[vertex_range.iter()],
+                colors: &self.colors[color_range.iter()],
+            },
+            Textured(ref texture, vertex_range, uv_range) => CommandRef::Textured {
+                texture,
+                vertices: &self.vertices[vertex_range.iter()],
+                uvs: &self.uvs[uv_range.iter()],
+            },
+            TexturedColor(ref texture, vertex_range, uv_range, color_range) => {
+                CommandRef::TexturedColor {
+                    texture,
+                    vertices: &self.vertices[vertex_range.iter()],
+                    uvs: &self.uvs[uv_range.iter()],
+                    colors: &self.colors[color_range.iter()],
+                }
+            }
+            Child(index) => CommandRef::Child(&self.children[index]),
+            Primitive(index) => CommandRef::Primitive(&self.primitives[index]),
+            PushTag(tag) => CommandRef::PushTag(tag),
+            PopTag => CommandRef::PopTag,
+        }
+    }
+}
+
+impl<'a> Iterator for Commands<'a> {
+    type Item = CommandRef<'a>;
+
+    fn next(&mut self) -> Option<CommandRef<'a>> {
+        self.iter.next().map(|command| self.tree.command_ref(command))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+impl<'a> ExactSizeIterator for Commands<'a> {}
+
+impl<'a> PartialEq for CommandRef<'a> {
+    /// Commands are equal when they have equal data,
+    /// while children and primitives are compared by identity.
+    fn eq(&self, other: &CommandRef<'a>) -> bool {
+        use self::CommandRef::*;
+
+        match (*self, *other) {
+            (ClearColor(a), ClearColor(b)) |
+            (ChangeColor(a), ChangeColor(b)) => a == b,
+            (ClearStencil(a), ClearStencil(b)) => a == b,
+            (ChangeDrawState(a), ChangeDrawState(b)) => a == b,
+            (Colored {vertices: a}, Colored {vertices: b}) => a == b,
+            (Colors {vertices: a, colors: ac}, Colors {vertices: b, colors: bc}) => {
+                a == b && ac == bc
+            }
+            (Textured {texture: at, vertices: a, uvs: auv},
+             Textured {texture: bt, vertices: b, uvs: buv}) => {
+                at == bt && a == b && auv == buv
+            }
+            (TexturedColor {texture: at, vertices: a, uvs: auv, colors: ac},
+             TexturedColor {texture: bt, vertices: b, uvs: buv, colors: bc}) => {
+                at == bt && a == b && auv == buv && ac == bc
+            }
+            (Child(a), Child(b)) => ptr::eq(a, b),
+            (Primitive(a), Primitive(b)) => ptr::eq(a, b),
+            (PushTag(a), PushTag(b)) => a == b,
+            (PopTag, PopTag) => true,
+            _ => false,
+        }
+    }
+}
+
+/// Appends data to a buffer, returning its range.
+fn extend<T: Copy>(buffer: &mut Vec<T>, data: &[T]) -> Range {
+    let start = buffer.len();
+    buffer.extend_from_slice(data);
+    Range::new(start, data.len())
+}
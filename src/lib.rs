@@ -6,26 +6,90 @@ extern crate graphics;
 extern crate image;
 extern crate range;
 extern crate texture;
+#[cfg(feature = "serde")]
+extern crate serde;
 
-use std::sync::{Arc, RwLock};
+use std::borrow::Cow;
+use std::error::Error;
+use std::fmt;
+use std::time::{Duration, Instant};
+use std::sync::{Arc, OnceLock, PoisonError, RwLock, Weak};
 use std::collections::HashMap;
 
-use graphics::{DrawState, Graphics, ImageSize};
-use graphics::types::Color;
+use graphics::{DrawState, Ellipse, Graphics, Image, ImageSize, Line, Polygon, Rectangle};
+use graphics::types::{self, Color, Matrix2d};
 use image::RgbaImage;
 use range::Range;
-use texture::CreateTexture;
+use texture::{CreateTexture, Format, TextureSettings, UpdateTexture};
+
+use atlas::{Atlas, Batch};
+use bounds::Bounds;
+use chunks::chunks;
+
+pub use capacity::{Capacity, DoubleBuffer};
+pub use dump::{
+    content_hash, BlendData, CommandData, DrawStateData, FilterData, FrameDump, LoadError,
+    StencilData, TextureData, TextureSettingsData, WrapData,
+};
+pub use hit::Hit;
+pub use inspect::{CommandRef, Commands};
+pub use node::Node;
+pub use pass::{Optimize, Pass, Pipeline, PromoteColors, RemoveOccluded, Reorder};
+#[cfg(feature = "software")]
+pub use pass::VerifyError;
+pub use primitive::{Primitive, PrimitiveKind};
+pub use segment::Segment;
+pub use stats::{Profile, Stats, Timing};
+#[cfg(feature = "software")]
+pub use software::Rasterizer;
+
+mod append;
+mod atlas;
+mod dump;
+mod gc;
+mod hit;
+mod bounds;
+mod capacity;
+mod chunks;
+mod inspect;
+mod node;
+mod occlusion;
+mod optimize;
+mod pass;
+mod primitive;
+mod promote;
+mod reorder;
+mod segment;
+#[cfg(feature = "software")]
+mod software;
+mod stats;
+mod svg;
+mod tag;
 
 /// A graphics backend that stores and optimizes commands
+///
+/// A graphics tree is `Send`, so it can be recorded on another thread,
+/// see `GraphicsTree::append`.
+/// Cloning a tree copies its commands and buffers, while textures are shared.
+#[derive(Clone, Debug)]
 pub struct GraphicsTree {
     commands: Vec<Command>,
+    cached_bounds: Vec<Option<Bounds>>,
     vertices: Vec<[f32; 2]>,
     uvs: Vec<[f32; 2]>,
     colors: Vec<[f32; 4]>,
     current_color: Color,
     current_draw_state: DrawState,
+    children: Vec<Node>,
+    primitives: Vec<Primitive>,
+    semantic: bool,
+    dirty_region: Option<Bounds>,
+    hit_grid: OnceLock<hit::Grid>,
+    tessellation: Vec<OnceLock<GraphicsTree>>,
+    runs: Vec<primitive::Run>,
 }
 
+#[derive(Clone, Debug)]
 enum Command {
     ClearColor(Color),
     ClearStencil(u8),
@@ -35,6 +99,10 @@ enum Command {
     Colors(Range, Range),
     Textured(Texture, Range, Range),
     TexturedColor(Texture, Range, Range, Range),
+    Child(usize),
+    Primitive(usize),
+    PushTag(u64),
+    PopTag,
 }
 
 /// Simplifies some common operations on textures.
@@ -47,28 +115,88 @@ pub struct TextureInner {
     pub id: Option<u64>,
     /// Whether the texture needs to be updated.
     pub needs_update: bool,
+    /// The rectangle `[x, y, w, h]` in pixels that changed since the last update.
+    ///
+    /// When `needs_update` is set and this is `None`, the whole image is uploaded.
+    pub dirty_rect: Option<[u32; 4]>,
     /// The image data associated with a texture.
     pub image: RgbaImage,
+    /// The settings used when creating the backend texture.
+    ///
+    /// Use `Texture::set_settings` to change them,
+    /// such that the backend texture is created again.
+    pub settings: TextureSettings,
+    /// Whether the backend texture needs to be created again.
+    pub needs_recreate: bool,
 }
 
 /// Stores textures.
 pub struct TextureBuffer<F, T> {
     /// The factory that creates textures.
     pub factory: F,
-    textures: HashMap<u64, T>,
+    textures: HashMap<u64, Entry<T>>,
     next_id: u64,
+    update: Option<UpdateFn<F, T>>,
+    tick: u64,
+    sweep_tick: u64,
+    memory_usage: usize,
+    memory_budget: Option<usize>,
+    atlas: Option<Atlas<T>>,
+}
+
+/// A backend texture with the data used to collect or evict it.
+struct Entry<T> {
+    texture: T,
+    handle: Weak<RwLock<TextureInner>>,
+    last_used: u64,
+    memory: usize,
+}
+
+/// An error when drawing to a backend.
+#[derive(Debug)]
+pub enum DrawError<E> {
+    /// The backend could not create a texture.
+    CreateTexture(E),
+    /// The lock of a texture was poisoned by a panic while editing it.
+    Poisoned,
+    /// A texture has an id that does not exist in the texture buffer,
+    /// usually because it was drawn with another texture buffer.
+    MissingTexture(u64),
 }
 
+impl<E: fmt::Debug> fmt::Display for DrawError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DrawError::CreateTexture(ref err) => write!(f, "Could not create texture: {:?}", err),
+            DrawError::Poisoned => write!(f, "Texture lock is poisoned"),
+            DrawError::MissingTexture(id) => write!(f, "Texture does not exist: {}", id),
+        }
+    }
+}
+
+impl<E: fmt::Debug> Error for DrawError<E> {}
+
+/// Uploads a sub-region of a texture, returning `false` on failure.
+type UpdateFn<F, T> = fn(&mut T, &mut F, &[u8], [u32; 2], [u32; 2]) -> bool;
+
 impl GraphicsTree {
     /// Creates a new graphics tree.
     pub fn new() -> GraphicsTree {
         GraphicsTree {
             commands: vec![],
+            cached_bounds: vec![],
             vertices: vec![],
             uvs: vec![],
             colors: vec![],
             current_color: [0.0; 4],
             current_draw_state: Default::default(),
+            children: vec![],
+            primitives: vec![],
+            semantic: false,
+            dirty_region: None,
+            hit_grid: OnceLock::new(),
+            tessellation: vec![],
+            runs: vec![],
         }
     }
 
@@ -80,15 +208,43 @@ impl GraphicsTree {
         self.colors.len() == 0
     }
 
-    /// Clears all graphics.
+    /// Clears all graphics and the dirty region.
     pub fn clear(&mut self) {
         self.commands.clear();
+        self.cached_bounds.clear();
         self.vertices.clear();
         self.uvs.clear();
         self.colors.clear();
+        self.children.clear();
+        self.primitives.clear();
+        self.hit_grid.take();
+        self.tessellation.clear();
+        self.runs.clear();
+        self.dirty_region = None;
+        self.current_color = [0.0; 4];
+        self.current_draw_state = Default::default();
+    }
+
+    /// Records a color change if the color differs from the current one.
+    fn change_color(&mut self, color: &Color) {
+        if color != &self.current_color {
+            self.push_command(Command::ChangeColor(*color));
+            self.current_color = *color;
+        }
+    }
+
+    /// Records a draw state change if it differs from the current one.
+    pub(crate) fn change_draw_state(&mut self, draw_state: &DrawState) {
+        if draw_state != &self.current_draw_state {
+            self.push_command(Command::ChangeDrawState(*draw_state));
+            self.current_draw_state = *draw_state;
+        }
     }
 
     /// Draws graphics to backend.
+    ///
+    /// Panics when a texture could not be created or looked up,
+    /// see `GraphicsTree::try_draw`.
     pub fn draw<F, T, G>(
         &self,
         texture_buffer: &mut TextureBuffer<F, T>,
@@ -97,211 +253,301 @@ impl GraphicsTree {
         where
             T: ImageSize + CreateTexture<F>,
             G: Graphics<Texture=T>
+    {
+        if let Err(err) = self.try_draw(texture_buffer, g) {
+            panic!("{}", err);
+        }
+    }
+
+    /// Draws graphics to backend, returning an error
+    /// when a texture could not be created or looked up.
+    ///
+    /// Drawing stops at the command that failed.
+    /// Textures whose handles were dropped are removed
+    /// from the texture buffer first, see `TextureBuffer::collect_garbage`.
+    pub fn try_draw<F, T, G>(
+        &self,
+        texture_buffer: &mut TextureBuffer<F, T>,
+        g: &mut G
+    ) -> Result<(), DrawError<T::Error>>
+        where
+            T: ImageSize + CreateTexture<F>,
+            G: Graphics<Texture=T>
+    {
+        texture_buffer.collect_garbage();
+        self.draw_transformed(None, None, None, None, texture_buffer, g)
+    }
+
+    /// Draws graphics to backend, skipping draw commands
+    /// whose bounds lie outside a viewport.
+    ///
+    /// The viewport `[x, y, w, h]` is in normalized device coordinates.
+    /// Panics when a texture could not be created or looked up,
+    /// see `GraphicsTree::try_draw`.
+    pub fn draw_with_viewport<F, T, G>(
+        &self,
+        viewport: [f32; 4],
+        texture_buffer: &mut TextureBuffer<F, T>,
+        g: &mut G
+    )
+        where
+            T: ImageSize + CreateTexture<F>,
+            G: Graphics<Texture=T>
+    {
+        if let Err(err) = self.try_draw_with_viewport(viewport, texture_buffer, g) {
+            panic!("{}", err);
+        }
+    }
+
+    /// Draws graphics to backend, skipping draw commands
+    /// whose bounds lie outside a viewport,
+    /// returning an error when a texture could not be created or looked up.
+    ///
+    /// See `GraphicsTree::draw_with_viewport` and `GraphicsTree::try_draw`.
+    pub fn try_draw_with_viewport<F, T, G>(
+        &self,
+        viewport: [f32; 4],
+        texture_buffer: &mut TextureBuffer<F, T>,
+        g: &mut G
+    ) -> Result<(), DrawError<T::Error>>
+        where
+            T: ImageSize + CreateTexture<F>,
+            G: Graphics<Texture=T>
+    {
+        texture_buffer.collect_garbage();
+        self.draw_transformed(None, Some(Bounds::from_rect(viewport)), None, None, texture_buffer, g)
+    }
+
+    /// Draws graphics to backend, transforming vertex positions
+    /// and skipping draw commands outside the viewport.
+    ///
+    /// The time spent per command is added to the profile, if any.
+    /// With a tag, only clears and draws recorded inside the tag are drawn.
+    pub(crate) fn draw_transformed<F, T, G>(
+        &self,
+        transform: Option<Matrix2d>,
+        viewport: Option<Bounds>,
+        tag: Option<u64>,
+        mut profile: Option<&mut Profile>,
+        texture_buffer: &mut TextureBuffer<F, T>,
+        g: &mut G
+    ) -> Result<(), DrawError<T::Error>>
+        where
+            T: ImageSize + CreateTexture<F>,
+            G: Graphics<Texture=T>
     {
         use Command::*;
-        use graphics::BACK_END_MAX_VERTEX_COUNT;
 
-        let bufsize = 2 * BACK_END_MAX_VERTEX_COUNT;
+        let transformed: Vec<[f32; 2]>;
+        let vertices = match transform {
+            None => &self.vertices,
+            Some(m) => {
+                transformed = self.vertices.iter()
+                    .map(|&v| node::transform_vertex(m, v))
+                    .collect();
+                &transformed
+            }
+        };
         let mut color: Color = [0.0; 4];
         let mut draw_state: DrawState = Default::default();
-        for command in &self.commands {
+        // The bounds are cached before transforming,
+        // so the transformed bounds are a conservative estimate.
+        let culled = |index: usize| match (viewport, self.cached_bounds[index]) {
+            (Some(viewport), Some(bounds)) => {
+                let bounds = transform.map_or(bounds, |m| bounds.transform(m));
+                !viewport.intersects(bounds)
+            }
+            _ => false,
+        };
+        let mut tags: Vec<u64> = vec![];
+        let tagged = |tags: &[u64]| tag.map_or(true, |tag| tags.contains(&tag));
+        let mut commands = self.commands.iter().enumerate().peekable();
+        while let Some((index, command)) = commands.next() {
+            // Runs of primitives are culled by the bounds of their merged draws.
+            if !matches!(*command, Primitive(_)) && culled(index) {continue}
+            match *command {
+                ChangeColor(_) | ChangeDrawState(_) | Child(_) | PushTag(_) | PopTag => {}
+                _ => if !tagged(&tags) {continue},
+            }
+            let start = profile.as_ref().map(|_| Instant::now());
             match *command {
                 ClearColor(color) => g.clear_color(color),
                 ClearStencil(value) => g.clear_stencil(value),
                 ChangeColor(new_color) => color = new_color,
                 ChangeDrawState(new_draw_state) => draw_state = new_draw_state,
                 Colored(range) => {
-                    // Split range in chunks to respect `Graphics` interface.
-                    let offset = range.offset;
-                    let length = range.length;
-                    let chunks = length / bufsize;
+                    let vertices = &vertices[range.iter()];
                     g.tri_list(&draw_state, &color, |f| {
-                        for i in 0..chunks {
-                            let start = offset + chunks * i;
-                            let end = start + bufsize;
-                            f(&self.vertices[start..end]);
-                        }
-                        if chunks * bufsize < length {
-                            let start = chunks * bufsize;
-                            let len = length - start;
-                            f(&self.vertices[offset + start..offset + len]);
+                        for chunk in chunks(range.length) {
+                            f(&vertices[chunk]);
                         }
                     });
                 }
                 Colors(vertex_range, color_range) => {
-                    let offset_v = vertex_range.offset;
-                    let length_v = vertex_range.length;
-                    let chunks_v = length_v / bufsize;
-                    let offset_c = color_range.offset;
-                    let length_c = color_range.length;
-                    let chunks_c = length_c / bufsize;
+                    let vertices = &vertices[vertex_range.iter()];
+                    let colors = &self.colors[color_range.iter()];
                     g.tri_list_c(&draw_state, |f| {
-                        for i in 0..chunks_v {
-                            let start_v = offset_v + chunks_v * i;
-                            let end_v = start_v + bufsize;
-                            let start_c = offset_c + chunks_c * i;
-                            let end_c = start_c + bufsize;
-                            f(&self.vertices[start_v..end_v], &self.colors[start_c..end_c]);
-                        }
-                        if chunks_v * bufsize < length_v {
-                            let start_v = chunks_v * bufsize;
-                            let len_v = length_v - start_v;
-                            let start_c = chunks_c * bufsize;
-                            let len_c = length_c - start_c;
-                            f(&self.vertices[offset_v + start_v..offset_v + len_v],
-                              &self.colors[offset_c + start_c..offset_c + len_c]);
+                        for chunk in chunks(vertex_range.length) {
+                            f(&vertices[chunk.clone()], &colors[chunk]);
                         }
                     });
                 }
                 Textured(ref tex, vertex_range, uv_range) => {
-                    // Split range in chunks to respect `Graphics` interface.
-                    let offset_v = vertex_range.offset;
-                    let length_v = vertex_range.length;
-                    let chunks_v = length_v / bufsize;
-                    let offset_uv = uv_range.offset;
-                    let length_uv = uv_range.length;
-                    let chunks_uv = length_uv / bufsize;
-
-                    let texture = if let Ok(mut inner) = tex.0.write() {
-                        if inner.id.is_none() {
-                            use texture::{Format, TextureSettings};
-
-                            let (width, height) = inner.image.dimensions();
-                            let new_texture: T = CreateTexture::create(
-                                &mut texture_buffer.factory,
-                                Format::Rgba8,
-                                &inner.image,
-                                [width, height],
-                                &TextureSettings::new()
-                            ).unwrap_or_else(|_| panic!("Could not create texture"));
-                            texture_buffer.textures.insert(texture_buffer.next_id, new_texture);
-                            inner.id = Some(texture_buffer.next_id);
-                            texture_buffer.next_id += 1;
-                        } else if inner.needs_update {
-                            // Create a new texture, because updating is not
-                            // supported directly yet.
-                            use texture::{Format, TextureSettings};
-
-                            let id = inner.id.unwrap();
-                            let (width, height) = inner.image.dimensions();
-                            let new_texture: T = CreateTexture::create(
-                                &mut texture_buffer.factory,
-                                Format::Rgba8,
-                                &inner.image,
-                                [width, height],
-                                &TextureSettings::new()
-                            ).unwrap_or_else(|_| panic!("Could not create texture"));
-                            texture_buffer.textures.insert(id, new_texture);
-                            inner.needs_update = false;
-                        }
-                        if let Some(texture) = texture_buffer.textures.get(&inner.id.unwrap()) {
-                            texture
-                        } else {
-                            panic!("Texture does not exist");
+                    let bound = texture_buffer.resolve(tex, &self.uvs[uv_range.iter()])?;
+                    if let Bound::Atlas(page, rect) = bound {
+                        let mut batch = Batch::new(texture_buffer.atlas.as_ref().unwrap().page_size());
+                        batch.push(&vertices[vertex_range.iter()], &self.uvs[uv_range.iter()], rect);
+                        // An error of a later draw is returned after drawing the batch.
+                        let mut error = None;
+                        while let Some(&(index, next_command @ &Textured(ref tex, vertex_range, uv_range))) =
+                            commands.peek()
+                        {
+                            if culled(index) {
+                                commands.next();
+                                continue;
+                            }
+                            match texture_buffer.resolve_without_eviction(tex, &self.uvs[uv_range.iter()]) {
+                                Ok(Bound::Atlas(next, rect)) if next == page => {
+                                    batch.push(&vertices[vertex_range.iter()],
+                                               &self.uvs[uv_range.iter()], rect);
+                                    if let Some(profile) = profile.as_deref_mut() {
+                                        profile.record(next_command, Duration::ZERO);
+                                    }
+                                }
+                                Ok(_) => break,
+                                Err(err) => {
+                                    error = Some(err);
+                                    break;
+                                }
+                            }
+                            commands.next();
                         }
+                        let texture = texture_buffer.bound_texture(bound)?;
+                        g.tri_list_uv(&draw_state, &color, texture, |f| {
+                            for chunk in chunks(batch.vertices.len()) {
+                                f(&batch.vertices[chunk.clone()], &batch.uvs[chunk]);
+                            }
+                        });
+                        if let Some(err) = error {return Err(err)}
                     } else {
-                        panic!("Image is used elsewhere");
-                    };
-
-                    g.tri_list_uv(&draw_state, &color, texture, |f| {
-                        for i in 0..chunks_v {
-                            let start_v = offset_v + chunks_v * i;
-                            let end_v = start_v + bufsize;
-                            let start_uv = offset_uv + chunks_uv * i;
-                            let end_uv = start_uv + bufsize;
-                            f(&self.vertices[start_v..end_v],
-                              &self.uvs[start_uv..end_uv]);
-                        }
-                        if chunks_v * bufsize < length_v {
-                            let start_v = chunks_v * bufsize;
-                            let len_v = length_v - start_v;
-                            let start_uv = chunks_uv * bufsize;
-                            let len_uv = length_uv - start_uv;
-                            f(&self.vertices[offset_v + start_v..offset_v + len_v],
-                              &self.uvs[offset_uv + start_uv..offset_uv + len_uv]);
-                        }
-                    });
+                        let texture = texture_buffer.bound_texture(bound)?;
+                        let vertices = &vertices[vertex_range.iter()];
+                        let uvs = &self.uvs[uv_range.iter()];
+                        g.tri_list_uv(&draw_state, &color, texture, |f| {
+                            for chunk in chunks(vertex_range.length) {
+                                f(&vertices[chunk.clone()], &uvs[chunk]);
+                            }
+                        });
+                    }
                 }
                 TexturedColor(ref tex, vertex_range, uv_range, color_range) => {
-                    // Split range in chunks to respect `Graphics` interface.
-                    let offset_v = vertex_range.offset;
-                    let length_v = vertex_range.length;
-                    let chunks_v = length_v / bufsize;
-                    let offset_uv = uv_range.offset;
-                    let length_uv = uv_range.length;
-                    let chunks_uv = length_uv / bufsize;
-                    let offset_c = color_range.offset;
-                    let length_c = color_range.length;
-                    let chunks_c = length_c / bufsize;
-
-                    let texture = if let Ok(mut inner) = tex.0.write() {
-                        if inner.id.is_none() {
-                            use texture::{Format, TextureSettings};
-
-                            let (width, height) = inner.image.dimensions();
-                            let new_texture: T = CreateTexture::create(
-                                &mut texture_buffer.factory,
-                                Format::Rgba8,
-                                &inner.image,
-                                [width, height],
-                                &TextureSettings::new()
-                            ).unwrap_or_else(|_| panic!("Could not create texture"));
-                            texture_buffer.textures.insert(texture_buffer.next_id, new_texture);
-                            inner.id = Some(texture_buffer.next_id);
-                            texture_buffer.next_id += 1;
-                        } else if inner.needs_update {
-                            // Create a new texture, because updating is not
-                            // supported directly yet.
-                            use texture::{Format, TextureSettings};
-
-                            let id = inner.id.unwrap();
-                            let (width, height) = inner.image.dimensions();
-                            let new_texture: T = CreateTexture::create(
-                                &mut texture_buffer.factory,
-                                Format::Rgba8,
-                                &inner.image,
-                                [width, height],
-                                &TextureSettings::new()
-                            ).unwrap_or_else(|_| panic!("Could not create texture"));
-                            texture_buffer.textures.insert(id, new_texture);
-                            inner.needs_update = false;
-                        }
-                        if let Some(texture) = texture_buffer.textures.get(&inner.id.unwrap()) {
-                            texture
-                        } else {
-                            panic!("Texture does not exist");
+                    let bound = texture_buffer.resolve(tex, &self.uvs[uv_range.iter()])?;
+                    if let Bound::Atlas(page, rect) = bound {
+                        let mut batch = Batch::new(texture_buffer.atlas.as_ref().unwrap().page_size());
+                        batch.push(&vertices[vertex_range.iter()], &self.uvs[uv_range.iter()], rect);
+                        // An error of a later draw is returned after drawing the batch.
+                        let mut error = None;
+                        batch.colors.extend_from_slice(&self.colors[color_range.iter()]);
+                        while let Some(&(index, next_command @ &TexturedColor(ref tex, vertex_range, uv_range, color_range))) =
+                            commands.peek()
+                        {
+                            if culled(index) {
+                                commands.next();
+                                continue;
+                            }
+                            match texture_buffer.resolve_without_eviction(tex, &self.uvs[uv_range.iter()]) {
+                                Ok(Bound::Atlas(next, rect)) if next == page => {
+                                    batch.push(&vertices[vertex_range.iter()],
+                                               &self.uvs[uv_range.iter()], rect);
+                                    batch.colors.extend_from_slice(&self.colors[color_range.iter()]);
+                                    if let Some(profile) = profile.as_deref_mut() {
+                                        profile.record(next_command, Duration::ZERO);
+                                    }
+                                }
+                                Ok(_) => break,
+                                Err(err) => {
+                                    error = Some(err);
+                                    break;
+                                }
+                            }
+                            commands.next();
                         }
+                        let texture = texture_buffer.bound_texture(bound)?;
+                        g.tri_list_uv_c(&draw_state, texture, |f| {
+                            for chunk in chunks(batch.vertices.len()) {
+                                f(&batch.vertices[chunk.clone()],
+                                  &batch.uvs[chunk.clone()],
+                                  &batch.colors[chunk]);
+                            }
+                        });
+                        if let Some(err) = error {return Err(err)}
                     } else {
-                        panic!("Image is used elsewhere");
-                    };
-
-                    g.tri_list_uv_c(&draw_state, texture, |f| {
-                        for i in 0..chunks_v {
-                            let start_v = offset_v + chunks_v * i;
-                            let end_v = start_v + bufsize;
-                            let start_uv = offset_uv + chunks_uv * i;
-                            let end_uv = start_uv + bufsize;
-                            let start_c = offset_c + chunks_c * i;
-                            let end_c = start_c + bufsize;
-                            f(&self.vertices[start_v..end_v],
-                              &self.uvs[start_uv..end_uv],
-                              &self.colors[start_c..end_c]);
-                        }
-                        if chunks_v * bufsize < length_v {
-                            let start_v = chunks_v * bufsize;
-                            let len_v = length_v - start_v;
-                            let start_uv = chunks_uv * bufsize;
-                            let len_uv = length_uv - start_uv;
-                            let start_c = chunks_c * bufsize;
-                            let len_c = length_c - start_c;
-                            f(&self.vertices[offset_v + start_v..offset_v + len_v],
-                              &self.uvs[offset_uv + start_uv..offset_uv + len_uv],
-                              &self.colors[offset_c + start_c..offset_c + len_c]);
+                        let texture = texture_buffer.bound_texture(bound)?;
+                        let vertices = &vertices[vertex_range.iter()];
+                        let uvs = &self.uvs[uv_range.iter()];
+                        let colors = &self.colors[color_range.iter()];
+                        g.tri_list_uv_c(&draw_state, texture, |f| {
+                            for chunk in chunks(vertex_range.length) {
+                                f(&vertices[chunk.clone()], &uvs[chunk.clone()], &colors[chunk]);
+                            }
+                        });
+                    }
+                }
+                Child(index) => {
+                    let node = &self.children[index];
+                    if node.visible {
+                        let m = node::compose(transform, node.transform);
+                        let tag = if tagged(&tags) {None} else {tag};
+                        node.tree.draw_transformed(m, viewport, tag, profile.as_deref_mut(), texture_buffer, g)?;
+                    }
+                }
+                Primitive(_) => {
+                    if let Some((end, triangles)) = self.primitive_run(index) {
+                        for (_, next_command) in commands.by_ref().take(end - index - 1) {
+                            if let Some(profile) = profile.as_deref_mut() {
+                                profile.record(next_command, Duration::ZERO);
+                            }
                         }
-                    });
+                        triangles.draw_transformed(transform, viewport, None, None, texture_buffer, g)?;
+                    }
                 }
+                PushTag(tag) => tags.push(tag),
+                PopTag => {tags.pop();}
+            }
+            if let (Some(profile), Some(start)) = (profile.as_deref_mut(), start) {
+                profile.record(command, start.elapsed());
             }
         }
+        Ok(())
+    }
+}
+
+impl Default for GraphicsTree {
+    fn default() -> GraphicsTree {
+        GraphicsTree::new()
+    }
+}
+
+impl Command {
+    /// Returns the vertex range of a draw command.
+    fn vertex_range(&self) -> Option<Range> {
+        use Command::*;
+
+        match *self {
+            ClearColor(_) | ClearStencil(_) |
+            ChangeColor(_) | ChangeDrawState(_) | Child(_) | Primitive(_) | PushTag(_) | PopTag => None,
+            Colored(range) |
+            Colors(range, _) |
+            Textured(_, range, _) |
+            TexturedColor(_, range, _, _) => Some(range),
+        }
+    }
+}
+
+impl PartialEq for Texture {
+    /// Textures are equal when they share the same inner data.
+    fn eq(&self, other: &Texture) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
     }
 }
 
@@ -309,7 +555,14 @@ impl ImageSize for Texture {
     fn get_size(&self) -> (u32, u32) {
         use std::ops::Deref;
 
-        self.0.read().unwrap().deref().image.dimensions()
+        self.0.read().unwrap_or_else(PoisonError::into_inner).deref().image.dimensions()
+    }
+}
+
+impl fmt::Debug for Texture {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let id = self.0.read().ok().and_then(|inner| inner.id);
+        f.debug_tuple("Texture").field(&id).finish()
     }
 }
 
@@ -317,11 +570,11 @@ impl Graphics for GraphicsTree {
     type Texture = Texture;
 
     fn clear_color(&mut self, color: Color) {
-        self.commands.push(Command::ClearColor(color));
+        self.push_command(Command::ClearColor(color));
     }
 
     fn clear_stencil(&mut self, value: u8) {
-        self.commands.push(Command::ClearStencil(value));
+        self.push_command(Command::ClearStencil(value));
     }
 
     fn tri_list<F>(
@@ -330,15 +583,11 @@ impl Graphics for GraphicsTree {
         color: &Color,
         mut f: F
     ) where F: FnMut(&mut dyn FnMut(&[[f32; 2]])) {
-        if color != &self.current_color {
-            self.commands.push(Command::ChangeColor(*color));
-        }
-        if draw_state != &self.current_draw_state {
-            self.commands.push(Command::ChangeDrawState(*draw_state));
-        }
+        self.change_color(color);
+        self.change_draw_state(draw_state);
         let start = self.vertices.len();
         f(&mut |chunk| self.vertices.extend_from_slice(chunk));
-        self.commands.push(Command::Colored(Range::new(start, self.vertices.len() - start)));
+        self.push_command(Command::Colored(Range::new(start, self.vertices.len() - start)));
     }
 
     fn tri_list_c<F>(
@@ -346,16 +595,14 @@ impl Graphics for GraphicsTree {
         draw_state: &DrawState,
         mut f: F
     ) where F: FnMut(&mut dyn FnMut(&[[f32; 2]], &[[f32; 4]])) {
-        if draw_state != &self.current_draw_state {
-            self.commands.push(Command::ChangeDrawState(*draw_state));
-        }
+        self.change_draw_state(draw_state);
         let start_v = self.vertices.len();
         let start_c = self.colors.len();
         f(&mut |chunk, chunk_color| {
             self.vertices.extend_from_slice(chunk);
             self.colors.extend_from_slice(chunk_color);
         });
-        self.commands.push(Command::Colors(
+        self.push_command(Command::Colors(
             Range::new(start_v, self.vertices.len() - start_v),
             Range::new(start_c, self.colors.len() - start_c)
         ));
@@ -368,19 +615,15 @@ impl Graphics for GraphicsTree {
         texture: &Self::Texture,
         mut f: F
     ) where F: FnMut(&mut dyn FnMut(&[[f32; 2]], &[[f32; 2]])) {
-        if color != &self.current_color {
-            self.commands.push(Command::ChangeColor(*color));
-        }
-        if draw_state != &self.current_draw_state {
-            self.commands.push(Command::ChangeDrawState(*draw_state));
-        }
+        self.change_color(color);
+        self.change_draw_state(draw_state);
         let start_vertices = self.vertices.len();
         let start_uvs = self.uvs.len();
         f(&mut |chunk, chunk_uvs| {
             self.vertices.extend_from_slice(chunk);
             self.uvs.extend_from_slice(chunk_uvs);
         });
-        self.commands.push(Command::Textured(
+        self.push_command(Command::Textured(
             texture.clone(),
             Range::new(start_vertices, self.vertices.len() - start_vertices),
             Range::new(start_uvs, self.uvs.len() - start_uvs)
@@ -393,9 +636,7 @@ impl Graphics for GraphicsTree {
         texture: &Self::Texture,
         mut f: F
     ) where F: FnMut(&mut dyn FnMut(&[[f32; 2]], &[[f32; 2]], &[[f32; 4]])) {
-        if draw_state != &self.current_draw_state {
-            self.commands.push(Command::ChangeDrawState(*draw_state));
-        }
+        self.change_draw_state(draw_state);
         let start_vertices = self.vertices.len();
         let start_uvs = self.uvs.len();
         let start_c = self.colors.len();
@@ -404,22 +645,93 @@ impl Graphics for GraphicsTree {
             self.uvs.extend_from_slice(chunk_uvs);
             self.colors.extend_from_slice(chunk_c);
         });
-        self.commands.push(Command::TexturedColor(
+        self.push_command(Command::TexturedColor(
             texture.clone(),
             Range::new(start_vertices, self.vertices.len() - start_vertices),
             Range::new(start_uvs, self.uvs.len() - start_uvs),
             Range::new(start_c, self.colors.len() - start_c)
         ));
     }
+
+    fn rectangle<R: Into<types::Rectangle>>(
+        &mut self,
+        r: &Rectangle,
+        rectangle: R,
+        draw_state: &DrawState,
+        transform: Matrix2d
+    ) {
+        if !self.semantic {return r.draw_tri(rectangle, draw_state, transform, self)}
+        self.push_primitive(Primitive {
+            kind: PrimitiveKind::Rectangle(*r, rectangle.into()),
+            draw_state: *draw_state,
+            transform,
+        });
+    }
+
+    fn polygon(
+        &mut self,
+        p: &Polygon,
+        polygon: types::Polygon<'_>,
+        draw_state: &DrawState,
+        transform: Matrix2d
+    ) {
+        if !self.semantic {return p.draw_tri(polygon, draw_state, transform, self)}
+        self.push_primitive(Primitive {
+            kind: PrimitiveKind::Polygon(*p, polygon.to_vec()),
+            draw_state: *draw_state,
+            transform,
+        });
+    }
+
+    fn image(
+        &mut self,
+        image: &Image,
+        texture: &Texture,
+        draw_state: &DrawState,
+        transform: Matrix2d
+    ) {
+        if !self.semantic {return image.draw_tri(texture, draw_state, transform, self)}
+        self.push_primitive(Primitive {
+            kind: PrimitiveKind::Image(*image, texture.clone()),
+            draw_state: *draw_state,
+            transform,
+        });
+    }
+
+    fn ellipse<R: Into<types::Rectangle>>(
+        &mut self,
+        e: &Ellipse,
+        rectangle: R,
+        draw_state: &DrawState,
+        transform: Matrix2d
+    ) {
+        if !self.semantic {return e.draw_tri(rectangle, draw_state, transform, self)}
+        self.push_primitive(Primitive {
+            kind: PrimitiveKind::Ellipse(*e, rectangle.into()),
+            draw_state: *draw_state,
+            transform,
+        });
+    }
+
+    fn line<L: Into<types::Line>>(
+        &mut self,
+        l: &Line,
+        line: L,
+        draw_state: &DrawState,
+        transform: Matrix2d
+    ) {
+        if !self.semantic {return l.draw_tri(line, draw_state, transform, self)}
+        self.push_primitive(Primitive {
+            kind: PrimitiveKind::Line(*l, line.into()),
+            draw_state: *draw_state,
+            transform,
+        });
+    }
 }
 
 impl From<RgbaImage> for Texture {
     fn from(image: RgbaImage) -> Texture {
-        Texture(Arc::new(RwLock::new(TextureInner {
-            id: None,
-            needs_update: false,
-            image: image
-        })))
+        Texture::with_settings(image, TextureSettings::new())
     }
 }
 
@@ -428,19 +740,319 @@ impl<F, T> TextureBuffer<F, T> {
     /// Creates a new `TextureBuffer`.
     pub fn new(factory: F) -> TextureBuffer<F, T> {
         TextureBuffer {
-            factory: factory,
+            factory,
             textures: HashMap::new(),
             next_id: 0,
+            update: None,
+            tick: 0,
+            sweep_tick: 0,
+            memory_usage: 0,
+            memory_budget: None,
+            atlas: None,
         }
     }
+
+    /// Creates a new `TextureBuffer` that updates changed textures in place.
+    ///
+    /// Only the changed region of an image is uploaded.
+    /// Textures are created again when the image size changed
+    /// or when updating fails.
+    pub fn with_update(factory: F) -> TextureBuffer<F, T>
+        where T: UpdateTexture<F>
+    {
+        TextureBuffer {update: Some(update_texture::<F, T>), ..TextureBuffer::new(factory)}
+    }
+
+    /// Packs textures up to `max_size` pixels wide and high
+    /// into shared pages of `page_size` pixels.
+    ///
+    /// Consecutive draws of textures on the same page are batched
+    /// into one draw call, with uvs mapped to the page when drawing.
+    /// Textures that repeat or use mipmaps are not packed.
+    /// Draws with uvs outside the image use a separate backend texture.
+    /// Pages are released when all their textures are dropped or swept,
+    /// see `TextureBuffer::sweep_unused`.
+    /// They count toward the memory usage, but are not evicted by the memory budget.
+    pub fn enable_atlas(&mut self, page_size: u32, max_size: u32) {
+        if self.atlas.is_none() {
+            self.atlas = Some(Atlas::new(page_size, max_size));
+        }
+    }
+
+    /// Looks up where a texture is stored, creating or updating it when needed,
+    /// then evicts other textures when over the memory budget.
+    ///
+    /// Draws are only batched in the atlas when their uvs are inside the image.
+    fn resolve(&mut self, tex: &Texture, uvs: &[[f32; 2]]) -> Result<Bound, DrawError<T::Error>>
+        where T: ImageSize + CreateTexture<F>
+    {
+        let bound = self.resolve_without_eviction(tex, uvs)?;
+        if let Bound::Texture(id) = bound {self.evict_over_budget(Some(id))}
+        Ok(bound)
+    }
+
+    /// Like `resolve`, but keeps textures over the memory budget.
+    ///
+    /// Used while batching draws in the atlas, such that the textures
+    /// of the batch are not evicted before it is drawn.
+    fn resolve_without_eviction(
+        &mut self,
+        tex: &Texture,
+        uvs: &[[f32; 2]]
+    ) -> Result<Bound, DrawError<T::Error>>
+        where T: ImageSize + CreateTexture<F>
+    {
+        let mut inner = tex.0.write().map_err(|_| DrawError::Poisoned)?;
+        let id = match inner.id {
+            Some(id) => id,
+            None => {
+                let id = self.next_id;
+                self.next_id += 1;
+                inner.id = Some(id);
+                inner.needs_update = true;
+                inner.dirty_rect = None;
+                id
+            }
+        };
+        let changed = inner.needs_update || inner.needs_recreate;
+        let mut create = changed;
+        if let Some(ref mut atlas) = self.atlas {
+            if !atlas::inside_image(uvs) {
+                // Use a separate texture, keeping the slot when the image is unchanged.
+                if changed {
+                    self.memory_usage -= atlas.free(id);
+                } else if atlas.slot(id).is_some() {
+                    create = !self.textures.contains_key(&id);
+                }
+            } else if !changed {
+                if let Some((page, rect)) = atlas.use_slot(id, self.tick + 1) {
+                    self.tick += 1;
+                    return Ok(Bound::Atlas(page, rect));
+                }
+            } else if atlas.accepts(&inner) {
+                let (placed, memory) = atlas.place(id, &inner, Arc::downgrade(&tex.0), self.tick + 1);
+                self.memory_usage -= memory;
+                if let Some((page, rect)) = placed {
+                    self.tick += 1;
+                    if let Some(old) = self.textures.remove(&id) {
+                        self.memory_usage -= old.memory;
+                    }
+                    inner.needs_update = false;
+                    inner.needs_recreate = false;
+                    inner.dirty_rect = None;
+                    return Ok(Bound::Atlas(page, rect));
+                }
+            } else {
+                self.memory_usage -= atlas.free(id);
+            }
+        }
+        if create {
+            let (width, height) = inner.image.dimensions();
+            let rect = inner.dirty_rect.unwrap_or([0, 0, width, height]);
+            let updated = !inner.needs_recreate && match (self.update, self.textures.get_mut(&id)) {
+                (Some(update), Some(entry)) if entry.texture.get_size() == (width, height) => {
+                    let memory: Cow<[u8]> = if rect == [0, 0, width, height] {
+                        Cow::Borrowed(&inner.image)
+                    } else {
+                        Cow::Owned(sub_image(&inner.image, rect))
+                    };
+                    update(&mut entry.texture, &mut self.factory, &memory,
+                           [rect[0], rect[1]], [rect[2], rect[3]])
+                }
+                _ => false,
+            };
+            if !updated {
+                let new_texture: T = CreateTexture::create(
+                    &mut self.factory,
+                    Format::Rgba8,
+                    &inner.image,
+                    [width, height],
+                    &inner.settings
+                ).map_err(DrawError::CreateTexture)?;
+                let memory = width as usize * height as usize * 4;
+                self.memory_usage += memory;
+                let old = self.textures.insert(id, Entry {
+                    texture: new_texture,
+                    handle: Arc::downgrade(&tex.0),
+                    last_used: self.tick,
+                    memory,
+                });
+                if let Some(old) = old {self.memory_usage -= old.memory}
+            }
+            inner.needs_update = false;
+            inner.needs_recreate = false;
+            inner.dirty_rect = None;
+        }
+        self.tick += 1;
+        let tick = self.tick;
+        match self.textures.get_mut(&id) {
+            Some(entry) => entry.last_used = tick,
+            None => return Err(DrawError::MissingTexture(id)),
+        }
+        Ok(Bound::Texture(id))
+    }
+
+    /// Returns the backend texture of a resolved texture,
+    /// uploading the atlas page when it changed.
+    fn bound_texture(&mut self, bound: Bound) -> Result<&T, DrawError<T::Error>>
+        where T: ImageSize + CreateTexture<F>
+    {
+        let index = match bound {
+            Bound::Texture(id) => return Ok(&self.textures[&id].texture),
+            Bound::Atlas(index, _) => index,
+        };
+        let atlas = self.atlas.as_mut().unwrap();
+        let size = atlas.page_size();
+        let page = &mut atlas.pages[index];
+        if page.dirty {
+            let updated = match (self.update, page.texture.as_mut()) {
+                (Some(update), Some(texture)) => {
+                    update(texture, &mut self.factory, &page.image, [0, 0], [size, size])
+                }
+                _ => false,
+            };
+            if !updated {
+                let new_texture: T = CreateTexture::create(
+                    &mut self.factory,
+                    Format::Rgba8,
+                    &page.image,
+                    [size, size],
+                    &page.settings
+                ).map_err(DrawError::CreateTexture)?;
+                if page.texture.is_none() {
+                    self.memory_usage += size as usize * size as usize * 4;
+                }
+                page.texture = Some(new_texture);
+            }
+            page.dirty = false;
+        }
+        Ok(page.texture.as_ref().unwrap())
+    }
+}
+
+/// Where a texture is stored in a texture buffer.
+#[derive(Clone, Copy)]
+enum Bound {
+    /// A backend texture with an id.
+    Texture(u64),
+    /// A page of the atlas and the rectangle `[x, y, w, h]` in pixels.
+    Atlas(usize, [u32; 4]),
+}
+
+fn update_texture<F, T: UpdateTexture<F>>(
+    texture: &mut T,
+    factory: &mut F,
+    memory: &[u8],
+    offset: [u32; 2],
+    size: [u32; 2]
+) -> bool {
+    texture.update(factory, Format::Rgba8, memory, offset, size).is_ok()
+}
+
+/// Copies the pixels of a rectangle `[x, y, w, h]` into a new buffer.
+fn sub_image(image: &RgbaImage, rect: [u32; 4]) -> Vec<u8> {
+    let pixels: &[u8] = image;
+    let stride = image.width() as usize * 4;
+    let (x, w) = (rect[0] as usize * 4, rect[2] as usize * 4);
+    let mut res = Vec::with_capacity(w * rect[3] as usize);
+    for y in rect[1]..rect[1] + rect[3] {
+        let row = y as usize * stride + x;
+        res.extend_from_slice(&pixels[row..row + w]);
+    }
+    res
+}
+
+/// Returns the smallest rectangle `[x, y, w, h]` containing both.
+fn union_rect(a: [u32; 4], b: [u32; 4]) -> [u32; 4] {
+    let (x0, y0) = (a[0].min(b[0]), a[1].min(b[1]));
+    let x1 = (a[0] + a[2]).max(b[0] + b[2]);
+    let y1 = (a[1] + a[3]).max(b[1] + b[3]);
+    [x0, y0, x1 - x0, y1 - y0]
+}
+
+/// Returns the pixel of an image with nearest filtering
+/// at texture coordinates interpolated by barycentric coordinates,
+/// or `None` if the image is empty.
+pub(crate) fn texel(image: &RgbaImage, uvs: &[[f32; 2]], b: [f32; 3]) -> Option<[u8; 4]> {
+    let (w, h) = image.dimensions();
+    if w == 0 || h == 0 {return None}
+    let u = uvs[0][0] * b[0] + uvs[1][0] * b[1] + uvs[2][0] * b[2];
+    let v = uvs[0][1] * b[0] + uvs[1][1] * b[1] + uvs[2][1] * b[2];
+    let x = ((u * w as f32).floor().max(0.0) as u32).min(w - 1);
+    let y = ((v * h as f32).floor().max(0.0) as u32).min(h - 1);
+    Some(image.get_pixel(x, y).0)
+}
+
+/// Returns `true` if a draw state only writes to the stencil buffer.
+pub(crate) fn writes_stencil(draw_state: &DrawState) -> bool {
+    use graphics::draw_state::Stencil;
+
+    matches!(draw_state.stencil, Some(Stencil::Clip(_)) | Some(Stencil::Increment))
 }
 
 impl Texture {
+    /// Creates a texture with settings used when creating the backend texture.
+    pub fn with_settings(image: RgbaImage, settings: TextureSettings) -> Texture {
+        Texture(Arc::new(RwLock::new(TextureInner {
+            id: None,
+            needs_update: false,
+            dirty_rect: None,
+            image,
+            settings,
+            needs_recreate: false,
+        })))
+    }
+
+    /// Returns the settings used when creating the backend texture.
+    pub fn settings(&self) -> TextureSettings {
+        self.0.read().unwrap_or_else(PoisonError::into_inner).settings
+    }
+
+    /// Changes the settings, creating the backend texture again when drawn.
+    pub fn set_settings(&self, settings: TextureSettings) {
+        let mut inner = self.0.write().unwrap();
+        inner.settings = settings;
+        inner.needs_recreate = true;
+    }
+
     /// Edit image.
+    ///
+    /// The whole image is uploaded when drawn.
+    /// Use `with_region_mut` when only a part of the image changes.
     pub fn with_image_mut<F>(&self, f: F)
         where F: FnOnce(&mut RgbaImage) {
         let mut inner = self.0.write().unwrap();
         f(&mut inner.image);
         inner.needs_update = true;
+        inner.dirty_rect = None;
+    }
+
+    /// Edit pixels inside a rectangle `[x, y, w, h]` of the image.
+    ///
+    /// Only the rectangle is uploaded by `TextureBuffer::with_update`,
+    /// together with other regions changed since the last draw.
+    /// Pixels outside the rectangle should not be changed.
+    /// When the image is resized, the whole image is uploaded.
+    pub fn with_region_mut<F>(&self, rect: [u32; 4], f: F)
+        where F: FnOnce(&mut RgbaImage) {
+        let mut inner = self.0.write().unwrap();
+        let size = inner.image.dimensions();
+        f(&mut inner.image);
+        let rect = if size == inner.image.dimensions() {
+            let x = rect[0].min(size.0);
+            let y = rect[1].min(size.1);
+            let w = rect[2].min(size.0 - x);
+            let h = rect[3].min(size.1 - y);
+            if w == 0 || h == 0 {return}
+            Some([x, y, w, h])
+        } else {
+            None
+        };
+        inner.dirty_rect = match (inner.needs_update, inner.dirty_rect, rect) {
+            (false, _, rect) => rect,
+            (true, Some(a), Some(b)) => Some(union_rect(a, b)),
+            (true, _, _) => None,
+        };
+        inner.needs_update = true;
     }
 }
@@ -0,0 +1,224 @@
+//! Named child nodes of a graphics tree.
+
+use graphics::math::{identity, multiply};
+use graphics::types::{Color, Matrix2d};
+use graphics::DrawState;
+use range::Range;
+
+use {Command, GraphicsTree};
+
+/// A named child of a graphics tree.
+///
+/// A child is recorded once and drawn with its transform each time
+/// the parent is drawn, as long as it is visible.
+/// To update a child, clear its tree and record it again,
+/// without touching the rest of the parent.
+#[derive(Clone, Debug)]
+pub struct Node {
+    name: String,
+    /// The transform applied to vertex positions of the child.
+    ///
+    /// Vertex positions are in normalized device coordinates,
+    /// so this transform is applied after the transforms used when recording.
+    /// Scissor rectangles are not transformed.
+    pub transform: Matrix2d,
+    /// Whether the child is drawn.
+    pub visible: bool,
+    /// The recorded graphics of the child.
+    pub tree: GraphicsTree,
+    pub(crate) dirty: bool,
+}
+
+impl Node {
+    /// Creates a child of a tree, using the same recording settings.
+    pub(crate) fn new(name: &str, parent: &GraphicsTree) -> Node {
+        let mut tree = GraphicsTree::new();
+        tree.semantic = parent.semantic;
+        Node {
+            name: name.into(),
+            transform: identity(),
+            visible: true,
+            tree,
+            dirty: false,
+        }
+    }
+
+    /// Returns the name of the child, which is empty for segments.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns `true` if the child is found by a name.
+    fn has_name(&self, name: &str) -> bool {
+        !name.is_empty() && self.name == name
+    }
+}
+
+impl GraphicsTree {
+    /// Adds a named child at the current position in the command list.
+    ///
+    /// The child is drawn after the commands recorded so far
+    /// and before commands recorded later.
+    /// If a child with the same name exists, it is returned instead.
+    /// Children without a name are never returned, since those are segments.
+    pub fn add_child(&mut self, name: &str) -> &mut Node {
+        let found = self.children.iter().position(|node| node.has_name(name));
+        let index = match found {
+            Some(index) => index,
+            None => {
+                self.push_command(Command::Child(self.children.len()));
+                let node = Node::new(name, self);
+                self.children.push(node);
+                self.children.len() - 1
+            }
+        };
+        &mut self.children[index]
+    }
+
+    /// Returns the child with a name.
+    pub fn child(&self, name: &str) -> Option<&Node> {
+        self.children.iter().find(|node| node.has_name(name))
+    }
+
+    /// Returns the child with a name, for recording or changing transform.
+    pub fn child_mut(&mut self, name: &str) -> Option<&mut Node> {
+        self.children.iter_mut().find(|node| node.has_name(name))
+    }
+
+    /// Returns the children in the order they were added.
+    pub fn children(&self) -> &[Node] {
+        &self.children
+    }
+
+    /// Returns a graphics tree without children,
+    /// where the commands of visible children are inserted with their
+    /// transforms applied to vertex positions.
+    ///
+    /// Primitives are kept, with the transforms of children
+    /// applied to their transforms.
+    pub fn flatten(&self) -> GraphicsTree {
+        let mut res = GraphicsTree::new();
+        res.extend_from(self, None, false);
+        res
+    }
+
+    /// Appends the commands of another tree, flattening its children
+    /// and tessellating primitives if `tessellate` is `true`.
+    ///
+    /// The commands of the other tree start from the default state,
+    /// so the state is changed before each draw command when needed.
+    pub(crate) fn extend_from(
+        &mut self,
+        other: &GraphicsTree,
+        transform: Option<Matrix2d>,
+        tessellate: bool
+    ) {
+        use Command::*;
+
+        let mut color: Color = [0.0; 4];
+        let mut draw_state: DrawState = Default::default();
+        let vertices = |tree: &mut GraphicsTree, range: Range| {
+            let start = tree.vertices.len();
+            let src = &other.vertices[range.iter()];
+            match transform {
+                None => tree.vertices.extend_from_slice(src),
+                Some(m) => tree.vertices.extend(src.iter().map(|&v| transform_vertex(m, v))),
+            }
+            Range::new(start, range.length)
+        };
+        let uvs = |tree: &mut GraphicsTree, range: Range| {
+            let start = tree.uvs.len();
+            tree.uvs.extend_from_slice(&other.uvs[range.iter()]);
+            Range::new(start, range.length)
+        };
+        let colors = |tree: &mut GraphicsTree, range: Range| {
+            let start = tree.colors.len();
+            tree.colors.extend_from_slice(&other.colors[range.iter()]);
+            Range::new(start, range.length)
+        };
+        for command in &other.commands {
+            let command = match *command {
+                ClearColor(color) => ClearColor(color),
+                ClearStencil(value) => ClearStencil(value),
+                PushTag(tag) => PushTag(tag),
+                PopTag => PopTag,
+                ChangeColor(new_color) => {
+                    color = new_color;
+                    continue;
+                }
+                ChangeDrawState(new_draw_state) => {
+                    draw_state = new_draw_state;
+                    continue;
+                }
+                Child(index) => {
+                    let node = &other.children[index];
+                    if node.visible {
+                        self.extend_from(&node.tree, compose(transform, node.transform), tessellate);
+                    }
+                    continue;
+                }
+                Primitive(index) => {
+                    let primitive = &other.primitives[index];
+                    if tessellate {
+                        primitive.draw_tri(transform, self);
+                    } else {
+                        let mut primitive = primitive.clone();
+                        if let Some(m) = transform {
+                            primitive.transform = multiply(m, primitive.transform);
+                        }
+                        self.push_primitive(primitive);
+                    }
+                    continue;
+                }
+                Colored(v) => {
+                    self.change_state(&color, &draw_state);
+                    Colored(vertices(self, v))
+                }
+                Colors(v, c) => {
+                    self.change_state(&color, &draw_state);
+                    Colors(vertices(self, v), colors(self, c))
+                }
+                Textured(ref texture, v, uv) => {
+                    self.change_state(&color, &draw_state);
+                    Textured(texture.clone(), vertices(self, v), uvs(self, uv))
+                }
+                TexturedColor(ref texture, v, uv, c) => {
+                    self.change_state(&color, &draw_state);
+                    TexturedColor(
+                        texture.clone(),
+                        vertices(self, v),
+                        uvs(self, uv),
+                        colors(self, c)
+                    )
+                }
+            };
+            self.push_command(command);
+        }
+    }
+
+    /// Records color and draw state changes if they differ from the current ones.
+    pub(crate) fn change_state(&mut self, color: &Color, draw_state: &DrawState) {
+        self.change_color(color);
+        self.change_draw_state(draw_state);
+    }
+}
+
+/// Composes an optional parent transform with a child transform.
+///
+/// Returns `None` for the identity transform.
+pub(crate) fn compose(parent: Option<Matrix2d>, child: Matrix2d) -> Option<Matrix2d> {
+    let m = match parent {
+        None => child,
+        Some(parent) => multiply(parent, child),
+    };
+    if m == identity() {None} else {Some(m)}
+}
+
+/// Transforms a vertex position.
+pub(crate) fn transform_vertex(m: Matrix2d, v: [f32; 2]) -> [f32; 2] {
+    let (x, y) = (v[0] as f64, v[1] as f64);
+    [
+        (m[0][0] * x + m[0][1] * y + m[0][2]) as f32,
+        (m[1][0] * x + m[1][1] * y + m[1][2]) as f32,
+    ]
+}
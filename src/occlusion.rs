@@ -0,0 +1,141 @@
+//! Removal of draw commands hidden by later opaque draws.
+
+use graphics::draw_state::Blend;
+use graphics::DrawState;
+use graphics::types::Color;
+
+use bounds::Bounds;
+use {writes_stencil, Command, GraphicsTree};
+
+impl GraphicsTree {
+    /// Removes draw commands whose pixels are overwritten later.
+    ///
+    /// A draw command is removed when its bounds lie inside
+    /// a later opaque axis-aligned rectangle, or when it is followed
+    /// by `ClearColor`, recorded with the same tags,
+    /// such that the draw is never shown without the cover.
+    /// Rectangles are opaque when drawn with alpha 1,
+    /// alpha or no blending and without scissor or stencil.
+    /// Draw commands that write to the stencil buffer are kept.
+    /// Primitives can be removed, but do not hide other draws.
+    /// Unused state changes are removed afterwards.
+    ///
+    /// Children are kept and processed separately.
+    pub fn remove_occluded(&mut self) {
+        use Command::*;
+
+        for node in &mut self.children {
+            node.tree.remove_occluded();
+        }
+
+        // The state and the scope of tags each command is drawn with.
+        let mut states: Vec<(Color, DrawState, usize)> = Vec::with_capacity(self.commands.len());
+        let mut color: Color = [0.0; 4];
+        let mut draw_state: DrawState = Default::default();
+        let mut scopes: Vec<Vec<u64>> = vec![vec![]];
+        let mut tags: Vec<u64> = vec![];
+        let mut scope = 0;
+        for command in &self.commands {
+            match *command {
+                ChangeColor(new_color) => color = new_color,
+                ChangeDrawState(new_draw_state) => draw_state = new_draw_state,
+                PushTag(tag) => {
+                    tags.push(tag);
+                    scope = intern(&mut scopes, &tags);
+                }
+                PopTag => {
+                    tags.pop();
+                    scope = intern(&mut scopes, &tags);
+                }
+                _ => {}
+            }
+            states.push((color, draw_state, scope));
+        }
+
+        let mut keep = vec![true; self.commands.len()];
+        let mut occluders: Vec<(usize, Bounds)> = vec![];
+        // Whether a later clear is recorded in each scope.
+        let mut cleared = vec![false; scopes.len()];
+        for (index, command) in self.commands.iter().enumerate().rev() {
+            let (color, mut draw_state, scope) = states[index];
+            match *command {
+                ClearColor(_) => {
+                    keep[index] = !cleared[scope];
+                    cleared[scope] = true;
+                    continue;
+                }
+                Colored(_) | Colors(..) | Textured(..) | TexturedColor(..) => {}
+                Primitive(i) => draw_state = self.primitives[i].draw_state,
+                ChangeColor(_) | ChangeDrawState(_) | ClearStencil(_) | Child(_) |
+                PushTag(_) | PopTag => continue,
+            }
+            if writes_stencil(&draw_state) {continue}
+            let covered = cleared[scope] || self.cached_bounds[index].is_some_and(|bounds| {
+                occluders.iter().any(|&(s, occluder)| s == scope && occluder.contains(bounds))
+            });
+            if covered {
+                keep[index] = false;
+                continue;
+            }
+            if self.is_opaque(command, color, &draw_state) {
+                let range = command.vertex_range().unwrap();
+                occluders.extend(self.vertices[range.iter()].chunks(6)
+                    .filter_map(rectangle).map(|bounds| (scope, bounds)));
+            }
+        }
+
+        let commands = self.take_commands();
+        for (command, keep) in commands.into_iter().zip(keep) {
+            if keep {self.push_command(command)}
+        }
+        self.remove_redundant_state_changes();
+    }
+
+    /// Returns `true` if a draw command replaces the color of the pixels it covers.
+    fn is_opaque(&self, command: &Command, color: Color, draw_state: &DrawState) -> bool {
+        if draw_state.scissor.is_some() || draw_state.stencil.is_some() {return false}
+        match draw_state.blend {
+            None | Some(Blend::Alpha) => {}
+            _ => return false,
+        }
+        match *command {
+            Command::Colored(_) => color[3] == 1.0,
+            Command::Colors(_, colors) => self.colors[colors.iter()].iter().all(|c| c[3] == 1.0),
+            _ => false,
+        }
+    }
+}
+
+/// Returns the index of a stack of tags, adding it when it is new.
+fn intern(scopes: &mut Vec<Vec<u64>>, tags: &[u64]) -> usize {
+    match scopes.iter().position(|scope| scope == tags) {
+        Some(index) => index,
+        None => {
+            scopes.push(tags.to_vec());
+            scopes.len() - 1
+        }
+    }
+}
+
+/// Returns the bounds of two triangles that cover an axis-aligned rectangle.
+///
+/// Each triangle must use three corners of the rectangle,
+/// leaving out opposite corners, like `graphics::rectangle` does.
+fn rectangle(vertices: &[[f32; 2]]) -> Option<Bounds> {
+    if vertices.len() != 6 {return None}
+    let bounds = Bounds::from_vertices(vertices)?;
+    if bounds.min[0] == bounds.max[0] || bounds.min[1] == bounds.max[1] {return None}
+    // Corners are numbered by whether x and y are at the maximum.
+    let corner = |v: &[f32; 2]| -> Option<u8> {
+        let x = if v[0] == bounds.min[0] {0} else if v[0] == bounds.max[0] {1} else {return None};
+        let y = if v[1] == bounds.min[1] {0} else if v[1] == bounds.max[1] {2} else {return None};
+        Some(x | y)
+    };
+    let mut left_out = [0; 2];
+    for (triangle, left_out) in vertices.chunks(3).zip(&mut left_out) {
+        let (a, b, c) = (corner(&triangle[0])?, corner(&triangle[1])?, corner(&triangle[2])?);
+        if a == b || b == c || a == c {return None}
+        *left_out = 6 - a - b - c;
+    }
+    if left_out[0] ^ left_out[1] == 3 {Some(bounds)} else {None}
+}
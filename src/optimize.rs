@@ -0,0 +1,155 @@
+//! Optimization passes over recorded commands.
+
+use std::sync::Arc;
+
+use graphics::DrawState;
+use graphics::types::Color;
+use range::Range;
+
+use bounds::Bounds;
+use {Command, GraphicsTree};
+
+impl GraphicsTree {
+    /// Optimizes the recorded commands.
+    ///
+    /// Removes state changes that are not used by any draw command,
+    /// then coalesces adjacent draw commands of the same kind that share
+    /// color, draw state and texture into a single command,
+    /// such that `draw` issues fewer calls to the backend.
+    /// The rendered result is unchanged.
+    ///
+    /// Children are optimized separately.
+    pub fn optimize(&mut self) {
+        self.remove_redundant_state_changes();
+        self.merge_draws();
+        for node in &mut self.children {
+            node.tree.optimize();
+        }
+    }
+
+    /// Removes state changes that are overwritten before being used.
+    ///
+    /// A state change is moved to just before the first draw command
+    /// that uses it, and dropped if it does not change the state.
+    /// Draw commands without vertices are removed.
+    /// Color changes are only used by `Colored` and `Textured`,
+    /// while draw state changes are used by all draw commands.
+    pub(crate) fn remove_redundant_state_changes(&mut self) {
+        use Command::*;
+
+        let commands = self.take_commands();
+        let mut color: Color = [0.0; 4];
+        let mut draw_state: DrawState = Default::default();
+        let mut next_color: Option<Color> = None;
+        let mut next_draw_state: Option<DrawState> = None;
+        for command in commands {
+            let uses_color = match command {
+                ChangeColor(new_color) => {
+                    next_color = Some(new_color);
+                    continue;
+                }
+                ChangeDrawState(new_draw_state) => {
+                    next_draw_state = Some(new_draw_state);
+                    continue;
+                }
+                ClearColor(_) | ClearStencil(_) | Child(_) | Primitive(_) | PushTag(_) | PopTag => {
+                    self.push_command(command);
+                    continue;
+                }
+                Colored(_) | Textured(..) => true,
+                Colors(..) | TexturedColor(..) => false,
+            };
+            // Draw commands without vertices do not use the state.
+            if command.vertex_range().is_some_and(|range| range.is_empty()) {continue}
+            if uses_color {
+                if let Some(new_color) = next_color.take() {
+                    if new_color != color {
+                        self.push_command(ChangeColor(new_color));
+                        color = new_color;
+                    }
+                }
+            }
+            if let Some(new_draw_state) = next_draw_state.take() {
+                if new_draw_state != draw_state {
+                    self.push_command(ChangeDrawState(new_draw_state));
+                    draw_state = new_draw_state;
+                }
+            }
+            self.push_command(command);
+        }
+        // Trailing state changes are not used by any draw command,
+        // so continue recording from the state seen by the last draw.
+        self.current_color = color;
+        self.current_draw_state = draw_state;
+    }
+
+    /// Coalesces adjacent compatible draw commands.
+    ///
+    /// Any state change between two draw commands prevents merging.
+    fn merge_draws(&mut self) {
+        let commands = self.take_commands();
+        for command in commands {
+            if let Some(last) = self.commands.last_mut() {
+                if merge(last, &command) {
+                    let bounds = self.vertex_bounds(&command);
+                    let last = self.cached_bounds.last_mut().unwrap();
+                    *last = Bounds::union_opt(*last, bounds);
+                    continue;
+                }
+            }
+            self.push_command(command);
+        }
+    }
+}
+
+/// Returns `true` if range `b` starts where range `a` ends.
+///
+/// Range `a` must hold whole triangles, because a partial triangle
+/// at its end is not drawn, but would be completed by range `b`.
+fn adjacent(a: &Range, b: &Range) -> bool {
+    a.length % 3 == 0 && a.next_offset() == b.offset
+}
+
+/// Extends range `a` to include range `b`.
+fn extend(a: &mut Range, b: &Range) {
+    a.length += b.length;
+}
+
+/// Tries to merge draw command `b` into draw command `a`.
+///
+/// Commands are only merged when their data is stored contiguously,
+/// so no buffer needs to be rearranged.
+/// Returns `true` if the commands were merged.
+fn merge(a: &mut Command, b: &Command) -> bool {
+    use Command::*;
+
+    match (a, b) {
+        (Colored(av), Colored(bv)) => {
+            if !adjacent(av, bv) {return false}
+            extend(av, bv);
+            true
+        }
+        (Colors(av, ac), Colors(bv, bc)) => {
+            if !adjacent(av, bv) || !adjacent(ac, bc) {return false}
+            extend(av, bv);
+            extend(ac, bc);
+            true
+        }
+        (Textured(at, av, auv), Textured(bt, bv, buv)) => {
+            if !Arc::ptr_eq(&at.0, &bt.0) ||
+               !adjacent(av, bv) || !adjacent(auv, buv) {return false}
+            extend(av, bv);
+            extend(auv, buv);
+            true
+        }
+        (TexturedColor(at, av, auv, ac), TexturedColor(bt, bv, buv, bc)) => {
+            if !Arc::ptr_eq(&at.0, &bt.0) || !adjacent(av, bv) ||
+               !adjacent(auv, buv) || !adjacent(ac, bc) {return false}
+            extend(av, bv);
+            extend(auv, buv);
+            extend(ac, bc);
+            true
+        }
+        _ => false,
+    }
+}
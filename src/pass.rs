@@ -0,0 +1,158 @@
+//! Composable optimization passes.
+
+#[cfg(feature = "software")]
+use std::error::Error;
+#[cfg(feature = "software")]
+use std::fmt;
+
+use GraphicsTree;
+
+/// An optimization pass over the recorded commands and buffers of a tree.
+///
+/// A pass should not change the rendered result.
+/// Any closure taking a `&mut GraphicsTree` is a pass.
+///
+/// A pass can rewrite commands by taking the recorded tree,
+/// for example with `std::mem::take`, and pushing the commands
+/// returned by `GraphicsTree::commands` with `GraphicsTree::push`.
+pub trait Pass {
+    /// Returns the name of the pass, used when reporting errors.
+    fn name(&self) -> &str {
+        std::any::type_name::<Self>()
+    }
+
+    /// Runs the pass on a tree.
+    fn run(&mut self, tree: &mut GraphicsTree);
+}
+
+impl<F> Pass for F where F: FnMut(&mut GraphicsTree) {
+    fn run(&mut self, tree: &mut GraphicsTree) {
+        self(tree)
+    }
+}
+
+/// Removes redundant state changes and merges draws, see `GraphicsTree::optimize`.
+#[derive(Clone, Copy, Debug)]
+pub struct Optimize;
+
+impl Pass for Optimize {
+    fn name(&self) -> &str {"optimize"}
+    fn run(&mut self, tree: &mut GraphicsTree) {tree.optimize()}
+}
+
+/// Removes covered draws, see `GraphicsTree::remove_occluded`.
+#[derive(Clone, Copy, Debug)]
+pub struct RemoveOccluded;
+
+impl Pass for RemoveOccluded {
+    fn name(&self) -> &str {"remove_occluded"}
+    fn run(&mut self, tree: &mut GraphicsTree) {tree.remove_occluded()}
+}
+
+/// Groups draws that do not overlap, see `GraphicsTree::reorder`.
+#[derive(Clone, Copy, Debug)]
+pub struct Reorder;
+
+impl Pass for Reorder {
+    fn name(&self) -> &str {"reorder"}
+    fn run(&mut self, tree: &mut GraphicsTree) {tree.reorder()}
+}
+
+/// Combines draws with different colors, see `GraphicsTree::promote_colors`.
+#[derive(Clone, Copy, Debug)]
+pub struct PromoteColors;
+
+impl Pass for PromoteColors {
+    fn name(&self) -> &str {"promote_colors"}
+    fn run(&mut self, tree: &mut GraphicsTree) {tree.promote_colors()}
+}
+
+/// A sequence of passes run in order.
+pub struct Pipeline {
+    passes: Vec<Box<dyn Pass + Send>>,
+}
+
+impl Pipeline {
+    /// Creates a pipeline without passes.
+    pub fn new() -> Pipeline {
+        Pipeline {passes: vec![]}
+    }
+
+    /// Adds a pass to the end of the pipeline.
+    pub fn add_pass<P>(&mut self, pass: P) where P: Pass + Send + 'static {
+        self.passes.push(Box::new(pass));
+    }
+
+    /// Returns the names of the passes in order.
+    pub fn names(&self) -> Vec<&str> {
+        self.passes.iter().map(|pass| pass.name()).collect()
+    }
+
+    /// Runs the passes on a tree.
+    pub fn run(&mut self, tree: &mut GraphicsTree) {
+        for pass in &mut self.passes {
+            pass.run(tree);
+        }
+    }
+
+    /// Runs the passes on a tree, checking that each pass
+    /// preserves the rendered result of the recorded input.
+    ///
+    /// The tree is rendered with the software rasterizer at a size in pixels.
+    /// Stops at the first pass that changes the rendered result,
+    /// restoring the tree as it was before running the pipeline.
+    ///
+    /// Requires the `software` feature.
+    #[cfg(feature = "software")]
+    pub fn run_verified(
+        &mut self,
+        tree: &mut GraphicsTree,
+        size: [u32; 2]
+    ) -> Result<(), VerifyError> {
+        let expected = tree.rasterize(size[0], size[1]);
+        let original = tree.clone();
+        for pass in &mut self.passes {
+            pass.run(tree);
+            let image = tree.rasterize(size[0], size[1]);
+            let pixels = expected.pixels().zip(image.pixels()).filter(|&(a, b)| a != b).count();
+            if pixels > 0 {
+                *tree = original;
+                return Err(VerifyError {pass: pass.name().into(), pixels});
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Default for Pipeline {
+    /// Creates the default pipeline,
+    /// removing covered draws before grouping and merging the remaining ones.
+    fn default() -> Pipeline {
+        let mut pipeline = Pipeline::new();
+        pipeline.add_pass(RemoveOccluded);
+        pipeline.add_pass(Reorder);
+        pipeline
+    }
+}
+
+/// A pass changed the rendered result.
+///
+/// Requires the `software` feature.
+#[cfg(feature = "software")]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VerifyError {
+    /// The name of the pass.
+    pub pass: String,
+    /// The number of pixels that changed.
+    pub pixels: usize,
+}
+
+#[cfg(feature = "software")]
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Pass `{}` changed {} pixels", self.pass, self.pixels)
+    }
+}
+
+#[cfg(feature = "software")]
+impl Error for VerifyError {}
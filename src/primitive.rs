@@ -0,0 +1,229 @@
+//! Semantic recording of shapes and images.
+
+use std::f64::consts::SQRT_2;
+use std::fmt;
+use std::sync::OnceLock;
+
+use graphics::math::multiply;
+use graphics::types::{self, Matrix2d};
+use graphics::{DrawState, Ellipse, Graphics, Image, ImageSize, Line, Polygon, Rectangle};
+
+use bounds::Bounds;
+use {Command, GraphicsTree, Texture};
+
+/// A shape or image recorded with its draw state and transform.
+///
+/// Primitives are tessellated into triangles once,
+/// and the triangles are transformed when drawn.
+#[derive(Clone)]
+pub struct Primitive {
+    /// The shape or image.
+    pub kind: PrimitiveKind,
+    /// The draw state.
+    pub draw_state: DrawState,
+    /// The transform applied to the shape.
+    pub transform: Matrix2d,
+}
+
+/// Consecutive primitive commands, drawn from merged triangles,
+/// such that shapes sharing color and draw state are drawn with one call.
+#[derive(Clone, Debug)]
+pub(crate) struct Run {
+    /// The index of the first command.
+    start: usize,
+    /// The index after the last command.
+    end: usize,
+    /// The merged triangles, computed on first use.
+    triangles: OnceLock<GraphicsTree>,
+}
+
+impl Run {
+    /// Returns the memory in bytes allocated by the merged triangles, if computed.
+    pub fn memory_usage(&self) -> usize {
+        self.triangles.get().map_or(0, GraphicsTree::memory_usage)
+    }
+}
+
+/// The kinds of primitives and their parameters.
+#[derive(Clone)]
+pub enum PrimitiveKind {
+    /// A rectangle `[x, y, w, h]`.
+    Rectangle(Rectangle, types::Rectangle),
+    /// An ellipse inside a rectangle `[x, y, w, h]`.
+    Ellipse(Ellipse, types::Rectangle),
+    /// A line `[x1, y1, x2, y2]`.
+    Line(Line, types::Line),
+    /// A polygon.
+    Polygon(Polygon, Vec<[f64; 2]>),
+    /// An image.
+    Image(Image, Texture),
+}
+
+impl Primitive {
+    /// Tessellates the primitive, applying a parent transform if any.
+    pub(crate) fn draw_tri<G>(&self, parent: Option<Matrix2d>, g: &mut G)
+        where G: Graphics<Texture = Texture>
+    {
+        use self::PrimitiveKind::*;
+
+        let transform = match parent {
+            None => self.transform,
+            Some(m) => multiply(m, self.transform),
+        };
+        let draw_state = &self.draw_state;
+        match self.kind {
+            Rectangle(ref rectangle, rect) => rectangle.draw_tri(rect, draw_state, transform, g),
+            Ellipse(ref ellipse, rect) => ellipse.draw_tri(rect, draw_state, transform, g),
+            Line(ref line, points) => line.draw_tri(points, draw_state, transform, g),
+            Polygon(ref polygon, ref points) => polygon.draw_tri(points, draw_state, transform, g),
+            Image(ref image, ref texture) => image.draw_tri(texture, draw_state, transform, g),
+        }
+    }
+
+    /// Returns a graphics tree with the triangles of the primitive.
+    pub fn tessellate(&self) -> GraphicsTree {
+        let mut tree = GraphicsTree::new();
+        self.draw_tri(None, &mut tree);
+        tree
+    }
+
+    /// Computes bounds containing the triangles of the primitive,
+    /// without tessellating it.
+    pub(crate) fn bounds(&self) -> Option<Bounds> {
+        use self::PrimitiveKind::*;
+
+        let ([x, y, w, h], radius) = match self.kind {
+            Rectangle(ref rectangle, rect) => (rect, rectangle.border.map_or(0.0, |border| border.radius)),
+            Ellipse(ref ellipse, rect) => (rect, ellipse.border.map_or(0.0, |border| border.radius)),
+            // Square ends reach past the end points diagonally.
+            Line(ref line, [x1, y1, x2, y2]) => ([x1, y1, x2 - x1, y2 - y1], line.radius * SQRT_2),
+            Polygon(_, ref points) => {
+                let (first, rest) = points.split_first()?;
+                let (min, max) = rest.iter().fold((*first, *first), |(min, max), p| {
+                    ([min[0].min(p[0]), min[1].min(p[1])], [max[0].max(p[0]), max[1].max(p[1])])
+                });
+                ([min[0], min[1], max[0] - min[0], max[1] - min[1]], 0.0)
+            }
+            Image(ref image, ref texture) => {
+                let rect = image.rectangle.unwrap_or_else(|| match image.source_rectangle {
+                    Some(source) => [0.0, 0.0, source[2], source[3]],
+                    None => {
+                        let (w, h) = texture.get_size();
+                        [0.0, 0.0, w as f64, h as f64]
+                    }
+                });
+                (rect, 0.0)
+            }
+        };
+        let min = [x.min(x + w) - radius, y.min(y + h) - radius];
+        let max = [x.max(x + w) + radius, y.max(y + h) + radius];
+        let bounds = Bounds {
+            min: [min[0] as f32, min[1] as f32],
+            max: [max[0] as f32, max[1] as f32],
+        };
+        Some(bounds.transform(self.transform))
+    }
+}
+
+impl fmt::Debug for Primitive {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self.kind {
+            PrimitiveKind::Rectangle(..) => "Rectangle",
+            PrimitiveKind::Ellipse(..) => "Ellipse",
+            PrimitiveKind::Line(..) => "Line",
+            PrimitiveKind::Polygon(..) => "Polygon",
+            PrimitiveKind::Image(..) => "Image",
+        };
+        f.debug_struct("Primitive")
+            .field("kind", &kind)
+            .field("draw_state", &self.draw_state)
+            .field("transform", &self.transform)
+            .finish()
+    }
+}
+
+impl GraphicsTree {
+    /// Sets whether shapes and images are recorded as primitives.
+    ///
+    /// When enabled, rectangles, ellipses, lines, polygons and images
+    /// drawn through `Graphics` are stored with their transforms
+    /// instead of as triangles. This keeps them exact when exported as SVG.
+    /// The triangles are computed on the first draw after recording
+    /// and kept until the commands change.
+    /// Children added afterwards use the same setting.
+    ///
+    /// Disabled by default.
+    pub fn set_semantic(&mut self, semantic: bool) {
+        self.semantic = semantic;
+    }
+
+    /// Returns `true` if shapes and images are recorded as primitives.
+    pub fn is_semantic(&self) -> bool {
+        self.semantic
+    }
+
+    /// Returns a graphics tree without children or primitives,
+    /// where primitives are tessellated into triangles.
+    ///
+    /// Visible children are flattened, see `GraphicsTree::flatten`.
+    pub fn tessellate(&self) -> GraphicsTree {
+        let mut res = GraphicsTree::new();
+        res.extend_from(self, None, true);
+        res
+    }
+
+    /// Returns the triangles of the primitive drawn by a command.
+    ///
+    /// Each primitive is tessellated on first use after recording.
+    pub(crate) fn tessellated(&self, command: usize) -> &GraphicsTree {
+        match self.commands[command] {
+            Command::Primitive(index) => {
+                self.tessellation[index].get_or_init(|| self.primitives[index].tessellate())
+            }
+            _ => unreachable!("Not a primitive"),
+        }
+    }
+
+    /// Returns the end and merged triangles of the run of primitives
+    /// starting at a command, or `None` if no run starts there.
+    ///
+    /// The triangles are computed on first use after the run changes.
+    pub(crate) fn primitive_run(&self, command: usize) -> Option<(usize, &GraphicsTree)> {
+        let run = &self.runs[self.runs.binary_search_by_key(&command, |run| run.start).ok()?];
+        let triangles = run.triangles.get_or_init(|| {
+            let mut tree = GraphicsTree::new();
+            for command in &self.commands[run.start..run.end] {
+                if let Command::Primitive(index) = *command {
+                    self.primitives[index].draw_tri(None, &mut tree);
+                }
+            }
+            tree.optimize();
+            tree
+        });
+        Some((run.end, triangles))
+    }
+
+    /// Updates the cached triangles for a command pushed at an index.
+    ///
+    /// Only the run extended by a primitive is invalidated.
+    pub(crate) fn push_run(&mut self, index: usize, command: &Command) {
+        if let Command::Primitive(primitive) = *command {
+            if self.tessellation.len() <= primitive {
+                self.tessellation.resize_with(primitive + 1, OnceLock::new);
+            }
+            match self.runs.last_mut() {
+                Some(run) if run.end == index => {
+                    run.end += 1;
+                    run.triangles.take();
+                }
+                _ => self.runs.push(Run {start: index, end: index + 1, triangles: OnceLock::new()}),
+            }
+        }
+    }
+
+    /// Records a primitive.
+    pub(crate) fn push_primitive(&mut self, primitive: Primitive) {
+        self.primitives.push(primitive);
+        self.push_command(Command::Primitive(self.primitives.len() - 1));
+    }
+}
@@ -0,0 +1,167 @@
+//! Promotion of single color draws to draws with a color per vertex.
+
+use std::sync::Arc;
+
+use graphics::DrawState;
+use graphics::types::Color;
+use range::Range;
+
+use {Command, GraphicsTree};
+
+impl GraphicsTree {
+    /// Combines runs of draws with different colors into single draws
+    /// with a color per vertex.
+    ///
+    /// Consecutive `Colored` and `Colors` draws sharing a draw state
+    /// become one `Colors` draw, and consecutive `Textured` and `TexturedColor`
+    /// draws sharing a draw state and texture become one `TexturedColor` draw.
+    /// The color of single color draws is repeated for each vertex,
+    /// which uses more memory but issues fewer calls to the backend.
+    /// Runs of single color draws that share a color are merged
+    /// without repeating the color.
+    /// A draw whose vertices do not form whole triangles ends its run.
+    ///
+    /// Children are processed separately.
+    pub fn promote_colors(&mut self) {
+        use Command::*;
+
+        for node in &mut self.children {
+            node.tree.promote_colors();
+        }
+
+        let commands = self.take_commands();
+        self.current_color = [0.0; 4];
+        self.current_draw_state = Default::default();
+
+        let mut color: Color = [0.0; 4];
+        let mut draw_state: DrawState = Default::default();
+        let mut run: Vec<(Command, Color)> = vec![];
+        for command in commands {
+            match command {
+                ChangeColor(new_color) => color = new_color,
+                ChangeDrawState(new_draw_state) => {
+                    if new_draw_state != draw_state {self.flush_run(&mut run, &draw_state)}
+                    draw_state = new_draw_state;
+                }
+                ClearColor(_) | ClearStencil(_) | Child(_) | Primitive(_) | PushTag(_) | PopTag => {
+                    self.flush_run(&mut run, &draw_state);
+                    self.push_command(command);
+                }
+                Colored(_) | Colors(..) | Textured(..) | TexturedColor(..) => {
+                    if !run.last().is_some_and(|(last, _)| same_run(last, &command)) {
+                        self.flush_run(&mut run, &draw_state);
+                    }
+                    // A partial triangle at the end of a draw is not drawn,
+                    // so it ends the run instead of being completed by the next draw.
+                    let partial = command.vertex_range().unwrap().length % 3 != 0;
+                    run.push((command, color));
+                    if partial {self.flush_run(&mut run, &draw_state)}
+                }
+            }
+        }
+        self.flush_run(&mut run, &draw_state);
+    }
+
+    /// Records a run of draws, combining them when there is more than one.
+    fn flush_run(&mut self, run: &mut Vec<(Command, Color)>, draw_state: &DrawState) {
+        use Command::*;
+
+        if run.len() == 1 {
+            let (command, color) = run.pop().unwrap();
+            match command {
+                Colored(_) | Textured(..) => self.change_state(&color, draw_state),
+                _ => self.change_draw_state(draw_state),
+            }
+            self.push_command(command);
+            return;
+        }
+        if run.is_empty() {return}
+
+        let color = run[0].1;
+        let uniform = run.iter().all(|&(ref command, c)| {
+            matches!(*command, Colored(_) | Textured(..)) && c == color
+        });
+        if uniform {
+            self.change_state(&color, draw_state);
+        } else {
+            self.change_draw_state(draw_state);
+        }
+        let vertices = self.join(run.iter().map(|(command, _)| command.vertex_range().unwrap()),
+                                 |tree| &mut tree.vertices);
+        let uvs = |tree: &mut GraphicsTree| tree.join(run.iter().map(|(command, _)| match *command {
+            Textured(_, _, range) | TexturedColor(_, _, range, _) => range,
+            _ => unreachable!(),
+        }), |tree| &mut tree.uvs);
+        if uniform {
+            let command = match run[0].0 {
+                Colored(_) => Colored(vertices),
+                Textured(ref texture, ..) => Textured(texture.clone(), vertices, uvs(self)),
+                _ => unreachable!(),
+            };
+            run.clear();
+            self.push_command(command);
+            return;
+        }
+        let colors = {
+            let start = self.colors.len();
+            for &(ref command, color) in run.iter() {
+                match *command {
+                    Colored(range) | Textured(_, range, _) => {
+                        self.colors.extend((0..range.length).map(|_| color));
+                    }
+                    Colors(_, range) | TexturedColor(_, _, _, range) => {
+                        self.colors.extend_from_within(range.iter());
+                    }
+                    _ => unreachable!(),
+                }
+            }
+            Range::new(start, self.colors.len() - start)
+        };
+        let command = match run[0].0 {
+            Colored(_) | Colors(..) => Colors(vertices, colors),
+            Textured(ref texture, ..) | TexturedColor(ref texture, ..) => {
+                TexturedColor(texture.clone(), vertices, uvs(self), colors)
+            }
+            _ => unreachable!(),
+        };
+        run.clear();
+        self.push_command(command);
+    }
+
+    /// Returns a range covering ranges of a buffer,
+    /// copying the data to the end of the buffer unless the ranges are adjacent.
+    fn join<I, B>(&mut self, ranges: I, buffer: B) -> Range
+        where I: Iterator<Item = Range> + Clone,
+              B: Fn(&mut GraphicsTree) -> &mut Vec<[f32; 2]>
+    {
+        let mut adjacent = ranges.clone();
+        let first = adjacent.next().unwrap();
+        let mut end = first.next_offset();
+        if adjacent.all(|range| {
+            let next = range.offset == end;
+            end = range.next_offset();
+            next
+        }) {
+            return Range::new(first.offset, end - first.offset);
+        }
+        let buffer = buffer(self);
+        let start = buffer.len();
+        for range in ranges {
+            buffer.extend_from_within(range.iter());
+        }
+        Range::new(start, buffer.len() - start)
+    }
+}
+
+/// Returns `true` if a draw can be combined with the previous draw.
+fn same_run(a: &Command, b: &Command) -> bool {
+    use Command::*;
+
+    match (a, b) {
+        (Colored(_) | Colors(..), Colored(_) | Colors(..)) => true,
+        (Textured(a, ..) | TexturedColor(a, ..), Textured(b, ..) | TexturedColor(b, ..)) => {
+            Arc::ptr_eq(&a.0, &b.0)
+        }
+        _ => false,
+    }
+}
@@ -0,0 +1,224 @@
+//! Reordering of draw commands that do not overlap.
+
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::mem;
+use std::sync::Arc;
+
+use graphics::DrawState;
+use graphics::types::Color;
+use range::Range;
+
+use bounds::Bounds;
+use dump::DrawStateData;
+use {Command, GraphicsTree, Texture};
+
+/// A draw command with the state it is drawn with.
+struct Item {
+    color: Color,
+    draw_state: DrawState,
+    command: Command,
+    bounds: Option<Bounds>,
+}
+
+impl GraphicsTree {
+    /// Reorders draw commands to group them by texture and state,
+    /// then optimizes the result, see `GraphicsTree::optimize`.
+    ///
+    /// A draw is only moved before another draw when their
+    /// bounding boxes do not overlap, so the rendered result is unchanged.
+    /// Draws are never moved across clears, children, primitives or tags.
+    ///
+    /// Children are reordered separately.
+    pub fn reorder(&mut self) {
+        use Command::*;
+
+        for node in &mut self.children {
+            node.tree.reorder();
+        }
+
+        let commands = self.take_commands();
+        let vertices = mem::take(&mut self.vertices);
+        let uvs = mem::take(&mut self.uvs);
+        let colors = mem::take(&mut self.colors);
+        self.current_color = [0.0; 4];
+        self.current_draw_state = Default::default();
+
+        let mut color: Color = [0.0; 4];
+        let mut draw_state: DrawState = Default::default();
+        let mut items: Vec<Item> = vec![];
+        for command in commands {
+            match command {
+                ChangeColor(new_color) => color = new_color,
+                ChangeDrawState(new_draw_state) => draw_state = new_draw_state,
+                ClearColor(_) | ClearStencil(_) | Child(_) | Primitive(_) | PushTag(_) | PopTag => {
+                    self.push_items(schedule(mem::take(&mut items)), &vertices, &uvs, &colors);
+                    self.push_command(command);
+                }
+                Colored(_) | Colors(..) | Textured(..) | TexturedColor(..) => {
+                    let bounds = command.vertex_range()
+                        .and_then(|range| Bounds::from_vertices(&vertices[range.iter()]));
+                    items.push(Item {color, draw_state, command, bounds});
+                }
+            }
+        }
+        self.push_items(schedule(items), &vertices, &uvs, &colors);
+        self.optimize();
+    }
+
+    /// Appends draw commands, copying their data from the old buffers.
+    fn push_items(
+        &mut self,
+        items: Vec<Item>,
+        vertices: &[[f32; 2]],
+        uvs: &[[f32; 2]],
+        colors: &[Color]
+    ) {
+        use Command::*;
+
+        for item in items {
+            self.change_state(&item.color, &item.draw_state);
+            let command = match item.command {
+                Colored(v) => Colored(copy(&mut self.vertices, vertices, v)),
+                Colors(v, c) => Colors(
+                    copy(&mut self.vertices, vertices, v),
+                    copy(&mut self.colors, colors, c)
+                ),
+                Textured(texture, v, uv) => Textured(
+                    texture,
+                    copy(&mut self.vertices, vertices, v),
+                    copy(&mut self.uvs, uvs, uv)
+                ),
+                TexturedColor(texture, v, uv, c) => TexturedColor(
+                    texture,
+                    copy(&mut self.vertices, vertices, v),
+                    copy(&mut self.uvs, uvs, uv),
+                    copy(&mut self.colors, colors, c)
+                ),
+                command => command,
+            };
+            self.push_command(command);
+        }
+    }
+}
+
+/// Copies a range of data to the end of a buffer, returning the new range.
+fn copy<T: Copy>(dst: &mut Vec<T>, src: &[T], range: Range) -> Range {
+    let start = dst.len();
+    dst.extend_from_slice(&src[range.iter()]);
+    Range::new(start, range.length)
+}
+
+/// Orders draws such that each draw follows the draws it overlaps,
+/// preferring to continue with a draw that can be merged with the last one.
+fn schedule(items: Vec<Item>) -> Vec<Item> {
+    let n = items.len();
+    // The number of earlier overlapping draws not yet scheduled,
+    // and the later draws that overlap each draw.
+    let mut waiting = vec![0; n];
+    let mut later: Vec<Vec<usize>> = vec![vec![]; n];
+    // Draws are indexed in a grid, such that only draws
+    // sharing a cell are tested for overlap.
+    if let Some(grid) = items.iter().fold(None, |acc, item| Bounds::union_opt(acc, item.bounds)) {
+        let size = Bounds::grid_size(n);
+        let mut cells: Vec<Vec<usize>> = vec![vec![]; size * size];
+        // The last draw tested against each draw, to test each pair once.
+        let mut tested = vec![usize::MAX; n];
+        for j in 0..n {
+            let b = match items[j].bounds {Some(b) => b, None => continue};
+            for cell in grid.cells(size, b) {
+                for &i in &cells[cell] {
+                    if tested[i] == j {continue}
+                    tested[i] = j;
+                    if overlaps(&items[i], &items[j]) {
+                        waiting[j] += 1;
+                        later[i].push(j);
+                    }
+                }
+                cells[cell].push(j);
+            }
+        }
+    }
+
+    // Ready draws by batch, and all ready draws, smallest index first.
+    // Scheduled draws are skipped when popped from the other heap.
+    let mut keys: HashMap<BatchKey, usize> = HashMap::new();
+    let batches: Vec<usize> = items.iter().map(|item| {
+        let len = keys.len();
+        *keys.entry(batch_key(item)).or_insert(len)
+    }).collect();
+    let mut ready_batch: Vec<BinaryHeap<Reverse<usize>>> = vec![BinaryHeap::new(); keys.len()];
+    let mut ready: BinaryHeap<Reverse<usize>> = BinaryHeap::new();
+    for i in (0..n).filter(|&i| waiting[i] == 0) {
+        ready_batch[batches[i]].push(Reverse(i));
+        ready.push(Reverse(i));
+    }
+    let mut scheduled = vec![false; n];
+    let mut order: Vec<usize> = Vec::with_capacity(n);
+    loop {
+        let next = order.last()
+            .and_then(|&last| pop(&mut ready_batch[batches[last]], &scheduled))
+            .or_else(|| pop(&mut ready, &scheduled));
+        let next = match next {Some(x) => x, None => break};
+        scheduled[next] = true;
+        order.push(next);
+        for &j in &later[next] {
+            waiting[j] -= 1;
+            if waiting[j] == 0 {
+                ready_batch[batches[j]].push(Reverse(j));
+                ready.push(Reverse(j));
+            }
+        }
+    }
+
+    let mut items: Vec<Option<Item>> = items.into_iter().map(Some).collect();
+    order.into_iter().map(|i| items[i].take().unwrap()).collect()
+}
+
+/// Pops the smallest index not yet scheduled.
+fn pop(heap: &mut BinaryHeap<Reverse<usize>>, scheduled: &[bool]) -> Option<usize> {
+    while let Some(Reverse(i)) = heap.pop() {
+        if !scheduled[i] {return Some(i)}
+    }
+    None
+}
+
+/// Returns `true` if the draws might cover the same pixels.
+///
+/// Touching bounding boxes count as overlapping,
+/// since both draws might cover pixels along the shared edge.
+fn overlaps(a: &Item, b: &Item) -> bool {
+    match (a.bounds, b.bounds) {
+        (Some(a), Some(b)) => a.intersects(b),
+        _ => false,
+    }
+}
+
+/// Identifies draws that can be merged when adjacent.
+#[derive(PartialEq, Eq, Hash)]
+struct BatchKey {
+    draw_state: DrawStateData,
+    kind: u8,
+    color: [u32; 4],
+    texture: usize,
+}
+
+/// Returns the batch key of a draw.
+///
+/// Colors are compared by bits, so draws with equal colors
+/// of different bits, like `0.0` and `-0.0`, are not batched.
+fn batch_key(item: &Item) -> BatchKey {
+    use Command::*;
+
+    let bits = |color: &Color| [color[0].to_bits(), color[1].to_bits(),
+                                color[2].to_bits(), color[3].to_bits()];
+    let ptr = |texture: &Texture| Arc::as_ptr(&texture.0) as *const () as usize;
+    let (kind, color, texture) = match item.command {
+        Colored(_) => (0, bits(&item.color), 0),
+        Colors(..) => (1, [0; 4], 0),
+        Textured(ref texture, ..) => (2, bits(&item.color), ptr(texture)),
+        TexturedColor(ref texture, ..) => (3, [0; 4], ptr(texture)),
+        _ => (4, [0; 4], 0),
+    };
+    BatchKey {draw_state: item.draw_state.into(), kind, color, texture}
+}
@@ -0,0 +1,108 @@
+//! Segments that can be invalidated and recorded again.
+
+use bounds::Bounds;
+use {Command, GraphicsTree, Node};
+
+/// A handle to a recorded segment of a graphics tree.
+///
+/// Segments are stored as children without a name,
+/// so they keep their position in the command list when recorded again.
+/// A handle is valid until the tree is cleared,
+/// or a child is removed with a tag, see `GraphicsTree::remove_tag`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Segment(usize);
+
+impl GraphicsTree {
+    /// Records a segment at the current position in the command list.
+    ///
+    /// Returns a handle used to invalidate and record the segment again.
+    pub fn record_segment<F>(&mut self, f: F) -> Segment
+        where F: FnOnce(&mut GraphicsTree)
+    {
+        let segment = Segment(self.children.len());
+        self.push_command(Command::Child(segment.0));
+        let node = Node::new("", self);
+        self.children.push(node);
+        self.rerecord_segment(segment, f);
+        segment
+    }
+
+    /// Marks a segment as dirty.
+    ///
+    /// The bounds of its current geometry are added to the dirty region.
+    pub fn invalidate(&mut self, segment: Segment) {
+        if self.children[segment.0].dirty {return}
+        let bounds = self.segment_bounds(segment);
+        self.add_dirty_bounds(bounds);
+        self.children[segment.0].dirty = true;
+    }
+
+    /// Returns `true` if a segment is marked as dirty.
+    pub fn is_dirty(&self, segment: Segment) -> bool {
+        self.children[segment.0].dirty
+    }
+
+    /// Records a segment again, replacing its previous commands.
+    ///
+    /// The bounds of both the previous and the new geometry
+    /// are added to the dirty region.
+    pub fn rerecord_segment<F>(&mut self, segment: Segment, f: F)
+        where F: FnOnce(&mut GraphicsTree)
+    {
+        self.invalidate(segment);
+        self.children[segment.0].tree.clear();
+        f(&mut self.children[segment.0].tree);
+        let bounds = self.segment_bounds(segment);
+        self.add_dirty_bounds(bounds);
+        self.children[segment.0].dirty = false;
+    }
+
+    /// Returns the segment's node, to change its transform or visibility.
+    ///
+    /// Call `invalidate` before changing the node and `rerecord_segment`
+    /// or `add_dirty_region` after, to keep the dirty region correct.
+    pub fn segment_node_mut(&mut self, segment: Segment) -> &mut Node {
+        &mut self.children[segment.0]
+    }
+
+    /// Returns the bounding box `[x, y, w, h]` of geometry that changed
+    /// since the dirty region was cleared, in normalized device coordinates.
+    pub fn dirty_region(&self) -> Option<[f32; 4]> {
+        self.dirty_region.map(|b| b.to_rect())
+    }
+
+    /// Returns the dirty region as a scissor rectangle `[x, y, w, h]`
+    /// in pixels, with the origin at the upper left corner.
+    ///
+    /// The rectangle is rounded outwards and clipped to the draw size.
+    /// Use it with `DrawState::scissor` to restrict redrawing.
+    pub fn dirty_scissor(&self, draw_size: [u32; 2]) -> Option<[u32; 4]> {
+        let b = self.dirty_region?;
+        let (w, h) = (draw_size[0] as f32, draw_size[1] as f32);
+        let x0 = ((b.min[0] + 1.0) * 0.5 * w).floor().clamp(0.0, w) as u32;
+        let x1 = ((b.max[0] + 1.0) * 0.5 * w).ceil().clamp(0.0, w) as u32;
+        let y0 = ((1.0 - b.max[1]) * 0.5 * h).floor().clamp(0.0, h) as u32;
+        let y1 = ((1.0 - b.min[1]) * 0.5 * h).ceil().clamp(0.0, h) as u32;
+        Some([x0, y0, x1 - x0, y1 - y0])
+    }
+
+    /// Adds a rectangle `[x, y, w, h]` in normalized device coordinates
+    /// to the dirty region.
+    pub fn add_dirty_region(&mut self, rect: [f32; 4]) {
+        self.add_dirty_bounds(Some(Bounds::from_rect(rect)));
+    }
+
+    /// Clears the dirty region, usually after redrawing it.
+    pub fn clear_dirty_region(&mut self) {
+        self.dirty_region = None;
+    }
+
+    fn add_dirty_bounds(&mut self, bounds: Option<Bounds>) {
+        self.dirty_region = Bounds::union_opt(self.dirty_region, bounds);
+    }
+
+    /// Computes the bounds of a segment in the parent's coordinates.
+    fn segment_bounds(&self, segment: Segment) -> Option<Bounds> {
+        self.child_bounds(segment.0)
+    }
+}
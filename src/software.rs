@@ -0,0 +1,258 @@
+//! Software rasterizer for recorded commands.
+//!
+//! Requires the `software` feature.
+
+use std::sync::PoisonError;
+
+use graphics::DrawState;
+use graphics::draw_state::{Blend, Stencil};
+use graphics::types::Color;
+use image::{Rgba, RgbaImage};
+
+use {texel, CommandRef, GraphicsTree};
+
+/// Renders recorded commands into an image on the CPU.
+///
+/// Vertex positions are mapped from normalized device coordinates,
+/// where `[-1, -1]` is the lower left corner and `[1, 1]` the upper right,
+/// to the pixels of the image.
+/// Scissor rectangles are in pixels with the origin at the upper left corner.
+///
+/// Textures are sampled from the image stored in the texture,
+/// using nearest filtering.
+pub struct Rasterizer {
+    image: RgbaImage,
+    stencil: Vec<u8>,
+}
+
+impl Rasterizer {
+    /// Creates a new rasterizer with a transparent image and zeroed stencil.
+    pub fn new(width: u32, height: u32) -> Rasterizer {
+        Rasterizer {
+            image: RgbaImage::new(width, height),
+            stencil: vec![0; width as usize * height as usize],
+        }
+    }
+
+    /// Returns the rendered image.
+    pub fn image(&self) -> &RgbaImage {
+        &self.image
+    }
+
+    /// Returns the stencil buffer, one value per pixel in row order.
+    pub fn stencil(&self) -> &[u8] {
+        &self.stencil
+    }
+
+    /// Consumes the rasterizer and returns the rendered image.
+    pub fn into_image(self) -> RgbaImage {
+        self.image
+    }
+
+    /// Replays the commands of a graphics tree.
+    ///
+    /// Children are flattened, see `GraphicsTree::flatten`.
+    pub fn draw(&mut self, tree: &GraphicsTree) {
+        let flat;
+        let tree = if tree.children().is_empty() {tree} else {
+            flat = tree.flatten();
+            &flat
+        };
+        let mut color: Color = [0.0; 4];
+        let mut draw_state: DrawState = Default::default();
+        for (index, command) in tree.commands().enumerate() {
+            match command {
+                CommandRef::ClearColor(clear) => {
+                    let pixel = Rgba(to_bytes(clear));
+                    for p in self.image.pixels_mut() {*p = pixel}
+                }
+                CommandRef::ClearStencil(value) => {
+                    for s in &mut self.stencil {*s = value}
+                }
+                CommandRef::PushTag(_) | CommandRef::PopTag => {}
+                CommandRef::ChangeColor(new_color) => color = new_color,
+                CommandRef::ChangeDrawState(new_draw_state) => draw_state = new_draw_state,
+                CommandRef::Colored {vertices} => {
+                    for tri in vertices.chunks_exact(3) {
+                        self.triangle(&draw_state, tri, |_| color);
+                    }
+                }
+                CommandRef::Colors {vertices, colors} => {
+                    for (tri, c) in vertices.chunks_exact(3).zip(colors.chunks_exact(3)) {
+                        self.triangle(&draw_state, tri, |b| interpolate(c, b));
+                    }
+                }
+                CommandRef::Textured {texture, vertices, uvs} => {
+                    let inner = texture.0.read().unwrap_or_else(PoisonError::into_inner);
+                    for (tri, uv) in vertices.chunks_exact(3).zip(uvs.chunks_exact(3)) {
+                        self.triangle(&draw_state, tri, |b| {
+                            mul(sample(&inner.image, uv, b), color)
+                        });
+                    }
+                }
+                CommandRef::TexturedColor {texture, vertices, uvs, colors} => {
+                    let inner = texture.0.read().unwrap_or_else(PoisonError::into_inner);
+                    let triangles = vertices.chunks_exact(3)
+                        .zip(uvs.chunks_exact(3))
+                        .zip(colors.chunks_exact(3));
+                    for ((tri, uv), c) in triangles {
+                        self.triangle(&draw_state, tri, |b| {
+                            mul(sample(&inner.image, uv, b), interpolate(c, b))
+                        });
+                    }
+                }
+                CommandRef::Child(_) => unreachable!("Children are flattened"),
+                CommandRef::Primitive(_) => self.draw(tree.tessellated(index)),
+            }
+        }
+    }
+
+    /// Fills a triangle, calling `shade` with barycentric coordinates
+    /// of each covered pixel center.
+    fn triangle<S>(&mut self, draw_state: &DrawState, tri: &[[f32; 2]], mut shade: S)
+        where S: FnMut([f32; 3]) -> Color
+    {
+        let (width, height) = self.image.dimensions();
+        let (w, h) = (width as f32, height as f32);
+        let p: Vec<[f32; 2]> = tri.iter()
+            .map(|v| [(v[0] + 1.0) * 0.5 * w, (1.0 - v[1]) * 0.5 * h])
+            .collect();
+        let area = edge(p[0], p[1], p[2]);
+        if area == 0.0 {return}
+
+        // Pixel bounds, restricted by scissor.
+        let (mut x0, mut y0, mut x1, mut y1) = (0, 0, width, height);
+        if let Some([sx, sy, sw, sh]) = draw_state.scissor {
+            x0 = sx.min(width);
+            y0 = sy.min(height);
+            x1 = sx.saturating_add(sw).min(width);
+            y1 = sy.saturating_add(sh).min(height);
+        }
+        let min_x = p.iter().map(|v| v[0]).fold(f32::INFINITY, f32::min);
+        let max_x = p.iter().map(|v| v[0]).fold(f32::NEG_INFINITY, f32::max);
+        let min_y = p.iter().map(|v| v[1]).fold(f32::INFINITY, f32::min);
+        let max_y = p.iter().map(|v| v[1]).fold(f32::NEG_INFINITY, f32::max);
+        x0 = x0.max(min_x.floor().max(0.0) as u32);
+        y0 = y0.max(min_y.floor().max(0.0) as u32);
+        x1 = x1.min(max_x.ceil().max(0.0) as u32);
+        y1 = y1.min(max_y.ceil().max(0.0) as u32);
+
+        for y in y0..y1 {
+            for x in x0..x1 {
+                let c = [x as f32 + 0.5, y as f32 + 0.5];
+                let b = [
+                    edge(p[1], p[2], c) / area,
+                    edge(p[2], p[0], c) / area,
+                    edge(p[0], p[1], c) / area,
+                ];
+                // Use a top-left fill rule, so shared edges are drawn once.
+                let inside = (0..3).all(|i| {
+                    b[i] > 0.0 || b[i] == 0.0 && top_left(p[(i + 1) % 3], p[(i + 2) % 3], area)
+                });
+                if !inside {continue}
+                self.fragment(draw_state, x, y, || shade(b));
+            }
+        }
+    }
+
+    /// Applies stencil test and blending to a single pixel.
+    fn fragment<S>(&mut self, draw_state: &DrawState, x: u32, y: u32, shade: S)
+        where S: FnOnce() -> Color
+    {
+        let index = (y * self.image.width() + x) as usize;
+        let stencil = &mut self.stencil[index];
+        match draw_state.stencil {
+            Some(Stencil::Clip(value)) => {
+                *stencil = value;
+                return;
+            }
+            Some(Stencil::Increment) => {
+                *stencil = stencil.saturating_add(1);
+                return;
+            }
+            Some(Stencil::Inside(value)) if *stencil != value => return,
+            Some(Stencil::Outside(value)) if *stencil == value => return,
+            _ => {}
+        }
+        let src = shade();
+        let pixel = self.image.get_pixel_mut(x, y);
+        let dst = from_bytes(pixel.0);
+        pixel.0 = to_bytes(blend(draw_state.blend, src, dst));
+    }
+}
+
+impl GraphicsTree {
+    /// Renders the graphics tree into an image on the CPU.
+    ///
+    /// Requires the `software` feature.
+    pub fn rasterize(&self, width: u32, height: u32) -> RgbaImage {
+        let mut rasterizer = Rasterizer::new(width, height);
+        rasterizer.draw(self);
+        rasterizer.into_image()
+    }
+}
+
+/// Blends a source color with a destination color.
+fn blend(blend: Option<Blend>, src: Color, dst: Color) -> Color {
+    let [sr, sg, sb, sa] = src;
+    let [dr, dg, db, da] = dst;
+    match blend {
+        None => src,
+        Some(Blend::Alpha) => [
+            sr * sa + dr * (1.0 - sa),
+            sg * sa + dg * (1.0 - sa),
+            sb * sa + db * (1.0 - sa),
+            sa + da,
+        ],
+        Some(Blend::Add) => [sr + dr, sg + dg, sb + db, sa + da],
+        Some(Blend::Lighter) => [sr * sa + dr, sg * sa + dg, sb * sa + db, da],
+        Some(Blend::Multiply) => [sr * dr, sg * dg, sb * db, sa * da],
+        Some(Blend::Invert) => [sr * (1.0 - dr), sg * (1.0 - dg), sb * (1.0 - db), da],
+    }
+}
+
+/// Twice the signed area of triangle `a, b, c`.
+fn edge(a: [f32; 2], b: [f32; 2], c: [f32; 2]) -> f32 {
+    (b[0] - a[0]) * (c[1] - a[1]) - (b[1] - a[1]) * (c[0] - a[0])
+}
+
+/// Returns `true` if edge `a, b` is a top or left edge.
+///
+/// The sign of the triangle area decides the winding.
+fn top_left(a: [f32; 2], b: [f32; 2], area: f32) -> bool {
+    let (dx, dy) = if area > 0.0 {(b[0] - a[0], b[1] - a[1])} else {(a[0] - b[0], a[1] - b[1])};
+    dy < 0.0 || dy == 0.0 && dx > 0.0
+}
+
+/// Interpolates vertex colors.
+///
+/// Equal colors are returned as is, since the weighted sum
+/// can differ slightly and round to another byte.
+fn interpolate(c: &[[f32; 4]], b: [f32; 3]) -> Color {
+    if c[0] == c[1] && c[1] == c[2] {return c[0]}
+    let mut res = [0.0; 4];
+    for (i, r) in res.iter_mut().enumerate() {
+        *r = c[0][i] * b[0] + c[1][i] * b[1] + c[2][i] * b[2];
+    }
+    res
+}
+
+/// Samples an image with nearest filtering at interpolated texture coordinates.
+fn sample(image: &RgbaImage, uv: &[[f32; 2]], b: [f32; 3]) -> Color {
+    texel(image, uv, b).map_or([0.0; 4], from_bytes)
+}
+
+/// Multiplies two colors component-wise.
+fn mul(a: Color, b: Color) -> Color {
+    [a[0] * b[0], a[1] * b[1], a[2] * b[2], a[3] * b[3]]
+}
+
+fn to_bytes(color: Color) -> [u8; 4] {
+    let f = |c: f32| (c.clamp(0.0, 1.0) * 255.0).round() as u8;
+    [f(color[0]), f(color[1]), f(color[2]), f(color[3])]
+}
+
+fn from_bytes(bytes: [u8; 4]) -> Color {
+    let f = |c: u8| c as f32 / 255.0;
+    [f(bytes[0]), f(bytes[1]), f(bytes[2]), f(bytes[3])]
+}
@@ -0,0 +1,209 @@
+//! Statistics about recorded commands and timing of draws.
+
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
+
+use graphics::{Graphics, ImageSize};
+use texture::CreateTexture;
+
+use chunks::chunks;
+use {Command, GraphicsTree, PrimitiveKind, TextureBuffer};
+
+/// Counts of recorded commands and data.
+///
+/// See `GraphicsTree::stats`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Stats {
+    /// The number of `ClearColor` commands.
+    pub clear_color: usize,
+    /// The number of `ClearStencil` commands.
+    pub clear_stencil: usize,
+    /// The number of color changes.
+    pub change_color: usize,
+    /// The number of draw state changes.
+    pub change_draw_state: usize,
+    /// The number of draws with a single color.
+    pub colored: usize,
+    /// The number of draws with a color per vertex.
+    pub colors: usize,
+    /// The number of textured draws with a single color.
+    pub textured: usize,
+    /// The number of textured draws with a color per vertex.
+    pub textured_color: usize,
+    /// The number of primitives.
+    pub primitives: usize,
+    /// The number of visible children.
+    pub children: usize,
+    /// The length of the vertex buffer.
+    pub vertex_count: usize,
+    /// The length of the uv buffer.
+    pub uv_count: usize,
+    /// The length of the color buffer.
+    pub color_count: usize,
+    /// The number of distinct textures.
+    pub textures: usize,
+    /// The estimated number of calls to the backend per draw.
+    ///
+    /// Each clear and draw command calls the backend once,
+    /// and runs of primitives are counted after merging their triangles.
+    /// Textured draws sharing an atlas page are batched when drawn,
+    /// so the backend might be called fewer times.
+    pub backend_calls: usize,
+    /// The number of vertex chunks passed to the backend per draw.
+    pub chunks: usize,
+}
+
+impl Stats {
+    /// Returns the number of color and draw state changes.
+    pub fn state_changes(&self) -> usize {
+        self.change_color + self.change_draw_state
+    }
+
+    /// Returns the number of draw commands.
+    pub fn draws(&self) -> usize {
+        self.colored + self.colors + self.textured + self.textured_color + self.primitives
+    }
+}
+
+/// The number of commands and the time spent drawing them.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Timing {
+    /// The number of drawn commands.
+    pub count: usize,
+    /// The time spent in total.
+    pub time: Duration,
+}
+
+/// Time spent in `draw`, broken down by command.
+///
+/// Textured draws include the time spent creating and updating textures.
+/// Draws batched in an atlas page and runs of primitives call the backend once,
+/// so their time is counted for the first command and the others add no time.
+/// See `GraphicsTree::draw_profiled`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Profile {
+    /// The time spent in `draw`.
+    pub total: Duration,
+    /// Clearing color and stencil.
+    pub clear: Timing,
+    /// Draws with a single color.
+    pub colored: Timing,
+    /// Draws with a color per vertex.
+    pub colors: Timing,
+    /// Textured draws with a single color.
+    pub textured: Timing,
+    /// Textured draws with a color per vertex.
+    pub textured_color: Timing,
+    /// Primitives, including tessellation.
+    pub primitives: Timing,
+}
+
+impl Profile {
+    /// Adds the time spent on a command.
+    pub(crate) fn record(&mut self, command: &Command, time: Duration) {
+        use Command::*;
+
+        let timing = match *command {
+            ClearColor(_) | ClearStencil(_) => &mut self.clear,
+            Colored(_) => &mut self.colored,
+            Colors(..) => &mut self.colors,
+            Textured(..) => &mut self.textured,
+            TexturedColor(..) => &mut self.textured_color,
+            Primitive(_) => &mut self.primitives,
+            ChangeColor(_) | ChangeDrawState(_) | Child(_) | PushTag(_) | PopTag => return,
+        };
+        timing.count += 1;
+        timing.time += time;
+    }
+}
+
+impl GraphicsTree {
+    /// Returns counts of recorded commands and data.
+    ///
+    /// Visible children are included.
+    pub fn stats(&self) -> Stats {
+        let mut stats = Stats::default();
+        let mut textures = HashSet::new();
+        self.add_stats(&mut stats, &mut textures);
+        stats.textures = textures.len();
+        stats
+    }
+
+    fn add_stats(&self, stats: &mut Stats, textures: &mut HashSet<*const ()>) {
+        use Command::*;
+
+        stats.vertex_count += self.vertices.len();
+        stats.uv_count += self.uvs.len();
+        stats.color_count += self.colors.len();
+        for (index, command) in self.commands.iter().enumerate() {
+            match *command {
+                ClearColor(_) => stats.clear_color += 1,
+                ClearStencil(_) => stats.clear_stencil += 1,
+                ChangeColor(_) => stats.change_color += 1,
+                ChangeDrawState(_) => stats.change_draw_state += 1,
+                Colored(_) => stats.colored += 1,
+                Colors(..) => stats.colors += 1,
+                Textured(ref texture, ..) => {
+                    stats.textured += 1;
+                    textures.insert(&*texture.0 as *const _ as *const ());
+                }
+                TexturedColor(ref texture, ..) => {
+                    stats.textured_color += 1;
+                    textures.insert(&*texture.0 as *const _ as *const ());
+                }
+                Child(index) => {
+                    let node = &self.children[index];
+                    if node.visible {
+                        stats.children += 1;
+                        node.tree.add_stats(stats, textures);
+                    }
+                    continue;
+                }
+                Primitive(primitive) => {
+                    stats.primitives += 1;
+                    if let PrimitiveKind::Image(_, ref texture) = self.primitives[primitive].kind {
+                        textures.insert(&*texture.0 as *const _ as *const ());
+                    }
+                    // Runs of primitives are drawn from merged triangles.
+                    if let Some((_, triangles)) = self.primitive_run(index) {
+                        let merged = triangles.stats();
+                        stats.backend_calls += merged.backend_calls;
+                        stats.chunks += merged.chunks;
+                    }
+                    continue;
+                }
+                PushTag(_) | PopTag => continue,
+            }
+            match *command {
+                ChangeColor(_) | ChangeDrawState(_) => {}
+                _ => stats.backend_calls += 1,
+            }
+            if let Some(range) = command.vertex_range() {
+                stats.chunks += chunks(range.length).count();
+            }
+        }
+    }
+
+    /// Draws graphics to backend, measuring the time spent per command.
+    ///
+    /// Panics when a texture could not be created or looked up,
+    /// see `GraphicsTree::try_draw`.
+    pub fn draw_profiled<F, T, G>(
+        &self,
+        texture_buffer: &mut TextureBuffer<F, T>,
+        g: &mut G
+    ) -> Profile
+        where
+            T: ImageSize + CreateTexture<F>,
+            G: Graphics<Texture=T>
+    {
+        let start = Instant::now();
+        let mut profile = Profile::default();
+        texture_buffer.collect_garbage();
+        if let Err(err) = self.draw_transformed(None, None, None, Some(&mut profile), texture_buffer, g) {
+            panic!("{}", err);
+        }
+        profile.total = start.elapsed();
+        profile
+    }
+}
@@ -0,0 +1,402 @@
+//! SVG export of recorded commands.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::io::Cursor;
+use std::sync::{Arc, PoisonError};
+
+use graphics::DrawState;
+use graphics::math::multiply;
+use graphics::types::Color;
+use image::{ImageFormat, RgbaImage};
+
+use {writes_stencil, CommandRef, GraphicsTree, Primitive, PrimitiveKind, Texture};
+
+/// The number of subdivisions along each edge of a triangle with vertex colors.
+const SUBDIVISIONS: usize = 4;
+
+impl GraphicsTree {
+    /// Exports the recorded commands as an SVG document.
+    ///
+    /// Vertex positions are mapped from normalized device coordinates
+    /// to a view box of `width` and `height` pixels.
+    ///
+    /// - Clears drop what was drawn before them,
+    ///   leaving a background rectangle with the clear color.
+    /// - Triangles using a single color become filled paths.
+    /// - Triangles with vertex colors are subdivided into smaller
+    ///   triangles using the interpolated color at their centers.
+    /// - Textured triangles embed the texture as a PNG image,
+    ///   mapped by the texture coordinates and clipped by the triangle.
+    ///   The color is applied with a color matrix filter.
+    ///   With vertex colors, the average color of each triangle is used.
+    ///
+    /// Scissor rectangles are exported as clip paths.
+    /// Draws to the stencil buffer are skipped and stencil tests are ignored.
+    /// Blend modes other than alpha blending are not supported.
+    /// Rectangles and ellipses without borders and lines without bevel ends
+    /// recorded as primitives become SVG shapes with their transforms,
+    /// other primitives are exported as triangles.
+    /// Children are flattened, see `GraphicsTree::flatten`.
+    pub fn to_svg(&self, width: u32, height: u32) -> String {
+        if !self.children.is_empty() {return self.flatten().to_svg(width, height)}
+
+        let mut svg = Svg {
+            width: width as f32,
+            height: height as f32,
+            defs: String::new(),
+            body: String::new(),
+            textures: HashMap::new(),
+            filters: HashMap::new(),
+            next_id: 0,
+        };
+        svg.commands(self);
+
+        let mut res = String::new();
+        let _ = writeln!(res, r#"<svg xmlns="http://www.w3.org/2000/svg" xmlns:xlink="http://www.w3.org/1999/xlink" width="{0}" height="{1}" viewBox="0 0 {0} {1}">"#, width, height);
+        if !svg.defs.is_empty() {
+            let _ = write!(res, "<defs>\n{}</defs>\n", svg.defs);
+        }
+        res.push_str(&svg.body);
+        res.push_str("</svg>\n");
+        res
+    }
+}
+
+struct Svg {
+    width: f32,
+    height: f32,
+    defs: String,
+    body: String,
+    // Maps texture pointers to image ids.
+    textures: HashMap<*const (), String>,
+    // Maps tint colors to filter ids.
+    filters: HashMap<[u32; 4], String>,
+    next_id: usize,
+}
+
+impl Svg {
+    /// Exports the commands of a tree without children.
+    fn commands(&mut self, tree: &GraphicsTree) {
+        let mut color: Color = [0.0; 4];
+        let mut draw_state: DrawState = Default::default();
+        for (index, command) in tree.commands().enumerate() {
+            match command {
+                CommandRef::ClearColor(clear) => {
+                    // A clear replaces the pixels drawn before it, including their alpha.
+                    self.body.clear();
+                    self.defs.clear();
+                    self.textures.clear();
+                    self.filters.clear();
+                    let _ = writeln!(self.body, r#"<rect width="{}" height="{}" {}/>"#,
+                        self.width, self.height, fill(clear));
+                }
+                CommandRef::ClearStencil(_) | CommandRef::PushTag(_) | CommandRef::PopTag => {}
+                CommandRef::ChangeColor(new_color) => color = new_color,
+                CommandRef::ChangeDrawState(new_draw_state) => draw_state = new_draw_state,
+                CommandRef::Colored {vertices} => {
+                    if writes_stencil(&draw_state) {continue}
+                    self.begin(&draw_state);
+                    let mut d = String::new();
+                    for tri in vertices.chunks_exact(3) {
+                        d.push_str(&self.path(tri));
+                    }
+                    let _ = writeln!(self.body, r#"<path d="{}" {}/>"#, d, fill(color));
+                    self.end(&draw_state);
+                }
+                CommandRef::Colors {vertices, colors} => {
+                    if writes_stencil(&draw_state) {continue}
+                    self.begin(&draw_state);
+                    for (tri, c) in vertices.chunks_exact(3).zip(colors.chunks_exact(3)) {
+                        self.gradient_triangle(tri, c);
+                    }
+                    self.end(&draw_state);
+                }
+                CommandRef::Textured {texture, vertices, uvs} => {
+                    if writes_stencil(&draw_state) {continue}
+                    self.begin(&draw_state);
+                    for (tri, uv) in vertices.chunks_exact(3).zip(uvs.chunks_exact(3)) {
+                        self.textured_triangle(texture, tri, uv, color);
+                    }
+                    self.end(&draw_state);
+                }
+                CommandRef::TexturedColor {texture, vertices, uvs, colors} => {
+                    if writes_stencil(&draw_state) {continue}
+                    self.begin(&draw_state);
+                    let triangles = vertices.chunks_exact(3)
+                        .zip(uvs.chunks_exact(3))
+                        .zip(colors.chunks_exact(3));
+                    for ((tri, uv), c) in triangles {
+                        let mut avg = [0.0; 4];
+                        for (i, a) in avg.iter_mut().enumerate() {
+                            *a = (c[0][i] + c[1][i] + c[2][i]) / 3.0;
+                        }
+                        self.textured_triangle(texture, tri, uv, avg);
+                    }
+                    self.end(&draw_state);
+                }
+                CommandRef::Child(_) => unreachable!("Children are flattened"),
+                CommandRef::Primitive(primitive) => self.primitive(primitive, tree.tessellated(index)),
+            }
+        }
+    }
+
+    /// Exports a primitive as an SVG shape with its transform when possible,
+    /// or as its triangles otherwise.
+    fn primitive(&mut self, primitive: &Primitive, triangles: &GraphicsTree) {
+        use graphics::line::Shape as LineShape;
+        use graphics::rectangle::Shape as RectangleShape;
+
+        let draw_state = &primitive.draw_state;
+        if writes_stencil(draw_state) {return}
+        let (w, h) = (self.width as f64, self.height as f64);
+        let m = multiply([[0.5 * w, 0.0, 0.5 * w], [0.0, -0.5 * h, 0.5 * h]], primitive.transform);
+        let transform = format!(r#"transform="matrix({} {} {} {} {} {})""#,
+            num(m[0][0] as f32), num(m[1][0] as f32), num(m[0][1] as f32),
+            num(m[1][1] as f32), num(m[0][2] as f32), num(m[1][2] as f32));
+        let shape = match primitive.kind {
+            PrimitiveKind::Rectangle(ref rectangle, rect) if rectangle.border.is_none() => {
+                let radius = match rectangle.shape {
+                    RectangleShape::Square => 0.0,
+                    RectangleShape::Round(radius, _) => radius,
+                    RectangleShape::Bevel(_) => return self.commands(triangles),
+                };
+                let [x, y, w, h] = rect;
+                let (x, w) = if w < 0.0 {(x + w, -w)} else {(x, w)};
+                let (y, h) = if h < 0.0 {(y + h, -h)} else {(y, h)};
+                let mut res = format!(r#"<rect x="{}" y="{}" width="{}" height="{}""#,
+                    num(x as f32), num(y as f32), num(w as f32), num(h as f32));
+                if radius > 0.0 {
+                    let _ = write!(res, r#" rx="{}""#, num(radius as f32));
+                }
+                let _ = write!(res, " {}", fill(rectangle.color));
+                res
+            }
+            PrimitiveKind::Ellipse(ref ellipse, [x, y, w, h]) if ellipse.border.is_none() => {
+                format!(r#"<ellipse cx="{}" cy="{}" rx="{}" ry="{}" {}"#,
+                    num((x + 0.5 * w) as f32), num((y + 0.5 * h) as f32),
+                    num((0.5 * w).abs() as f32), num((0.5 * h).abs() as f32),
+                    fill(ellipse.color))
+            }
+            PrimitiveKind::Line(ref line, [x1, y1, x2, y2]) => {
+                let cap = match line.shape {
+                    LineShape::Square => "butt",
+                    LineShape::Round | LineShape::RoundWithResolution(_) => "round",
+                    LineShape::Bevel => return self.commands(triangles),
+                };
+                format!(r#"<line x1="{}" y1="{}" x2="{}" y2="{}" stroke-width="{}" stroke-linecap="{}" {}"#,
+                    num(x1 as f32), num(y1 as f32), num(x2 as f32), num(y2 as f32),
+                    num(2.0 * line.radius as f32), cap, stroke(line.color))
+            }
+            _ => return self.commands(triangles),
+        };
+        self.begin(draw_state);
+        let _ = writeln!(self.body, "{} {}/>", shape, transform);
+        self.end(draw_state);
+    }
+
+    fn id(&mut self, prefix: &str) -> String {
+        self.next_id += 1;
+        format!("{}{}", prefix, self.next_id)
+    }
+
+    /// Maps a vertex from normalized device coordinates to pixels.
+    fn pos(&self, v: [f32; 2]) -> [f32; 2] {
+        [(v[0] + 1.0) * 0.5 * self.width, (1.0 - v[1]) * 0.5 * self.height]
+    }
+
+    /// Returns path data for a triangle with consistent winding,
+    /// such that overlapping triangles in one path do not cancel out.
+    fn path(&self, tri: &[[f32; 2]]) -> String {
+        let (a, mut b, mut c) = (self.pos(tri[0]), self.pos(tri[1]), self.pos(tri[2]));
+        if (b[0] - a[0]) * (c[1] - a[1]) - (b[1] - a[1]) * (c[0] - a[0]) < 0.0 {
+            std::mem::swap(&mut b, &mut c);
+        }
+        format!("M{} {}L{} {}L{} {}Z", num(a[0]), num(a[1]), num(b[0]), num(b[1]), num(c[0]), num(c[1]))
+    }
+
+    /// Starts a group clipped by the scissor rectangle.
+    fn begin(&mut self, draw_state: &DrawState) {
+        if let Some([x, y, w, h]) = draw_state.scissor {
+            let id = self.id("scissor");
+            let _ = writeln!(self.defs,
+                r#"<clipPath id="{}"><rect x="{}" y="{}" width="{}" height="{}"/></clipPath>"#,
+                id, x, y, w, h);
+            let _ = writeln!(self.body, r#"<g clip-path="url(#{})">"#, id);
+        }
+    }
+
+    /// Ends a group started by `begin`.
+    fn end(&mut self, draw_state: &DrawState) {
+        if draw_state.scissor.is_some() {
+            self.body.push_str("</g>\n");
+        }
+    }
+
+    /// Approximates a triangle with vertex colors by subdivision.
+    fn gradient_triangle(&mut self, tri: &[[f32; 2]], colors: &[[f32; 4]]) {
+        if colors[0] == colors[1] && colors[1] == colors[2] {
+            let _ = writeln!(self.body, r#"<path d="{}" {}/>"#, self.path(tri), fill(colors[0]));
+            return;
+        }
+        let n = SUBDIVISIONS as f32;
+        let lerp = |b: [f32; 3]| [
+            tri[0][0] * b[0] + tri[1][0] * b[1] + tri[2][0] * b[2],
+            tri[0][1] * b[0] + tri[1][1] * b[1] + tri[2][1] * b[2],
+        ];
+        for i in 0..SUBDIVISIONS {
+            for j in 0..SUBDIVISIONS - i {
+                let (fi, fj) = (i as f32, j as f32);
+                // Each cell has an upright triangle and, except at the edge,
+                // an inverted one.
+                let mut cells = vec![[[fi, fj], [fi + 1.0, fj], [fi, fj + 1.0]]];
+                if i + j + 1 < SUBDIVISIONS {
+                    cells.push([[fi + 1.0, fj], [fi + 1.0, fj + 1.0], [fi, fj + 1.0]]);
+                }
+                for cell in cells {
+                    let b: Vec<[f32; 3]> = cell.iter()
+                        .map(|&[u, v]| [u / n, v / n, 1.0 - (u + v) / n])
+                        .collect();
+                    let sub = [lerp(b[0]), lerp(b[1]), lerp(b[2])];
+                    let mut center = [0.0; 3];
+                    for (k, c) in center.iter_mut().enumerate() {
+                        *c = (b[0][k] + b[1][k] + b[2][k]) / 3.0;
+                    }
+                    let mut color = [0.0; 4];
+                    for (k, c) in color.iter_mut().enumerate() {
+                        *c = colors[0][k] * center[0] + colors[1][k] * center[1] +
+                             colors[2][k] * center[2];
+                    }
+                    let _ = writeln!(self.body, r#"<path d="{}" {}/>"#,
+                        self.path(&sub), fill(color));
+                }
+            }
+        }
+    }
+
+    /// Draws the part of a texture mapped to a triangle.
+    fn textured_triangle(
+        &mut self,
+        texture: &Texture,
+        tri: &[[f32; 2]],
+        uvs: &[[f32; 2]],
+        color: Color
+    ) {
+        let (image_id, w, h) = match self.texture(texture) {
+            Some(image) => image,
+            None => return,
+        };
+        // Source points in image pixels and destination points in view box pixels.
+        let s: Vec<[f32; 2]> = uvs.iter().map(|uv| [uv[0] * w, uv[1] * h]).collect();
+        let d: Vec<[f32; 2]> = tri.iter().map(|&v| self.pos(v)).collect();
+        let det = (s[1][0] - s[0][0]) * (s[2][1] - s[0][1]) -
+                  (s[2][0] - s[0][0]) * (s[1][1] - s[0][1]);
+        if det == 0.0 {return}
+        // Solve for the affine transform mapping source to destination.
+        let (su, sv) = ([s[1][0] - s[0][0], s[1][1] - s[0][1]], [s[2][0] - s[0][0], s[2][1] - s[0][1]]);
+        let (du, dv) = ([d[1][0] - d[0][0], d[1][1] - d[0][1]], [d[2][0] - d[0][0], d[2][1] - d[0][1]]);
+        let inv = [[sv[1] / det, -sv[0] / det], [-su[1] / det, su[0] / det]];
+        let a = du[0] * inv[0][0] + dv[0] * inv[1][0];
+        let c = du[0] * inv[0][1] + dv[0] * inv[1][1];
+        let b = du[1] * inv[0][0] + dv[1] * inv[1][0];
+        let dd = du[1] * inv[0][1] + dv[1] * inv[1][1];
+        let e = d[0][0] - a * s[0][0] - c * s[0][1];
+        let f = d[0][1] - b * s[0][0] - dd * s[0][1];
+
+        let clip_id = self.id("clip");
+        let _ = writeln!(self.defs, r#"<clipPath id="{}"><path d="{}"/></clipPath>"#,
+            clip_id, self.path(tri));
+        let filter = if color == [1.0; 4] {
+            String::new()
+        } else {
+            format!(r#" filter="url(#{})""#, self.filter(color))
+        };
+        let _ = writeln!(self.body,
+            r##"<g clip-path="url(#{})"><use xlink:href="#{}" transform="matrix({} {} {} {} {} {})"{}/></g>"##,
+            clip_id, image_id, num(a), num(b), num(c), num(dd), num(e), num(f), filter);
+    }
+
+    /// Embeds a texture once and returns its id and size,
+    /// or `None` if the image is empty.
+    fn texture(&mut self, texture: &Texture) -> Option<(String, f32, f32)> {
+        let inner = texture.0.read().unwrap_or_else(PoisonError::into_inner);
+        let (w, h) = inner.image.dimensions();
+        // Empty images can not be encoded and draw nothing.
+        if w == 0 || h == 0 {return None}
+        let ptr = Arc::as_ptr(&texture.0) as *const ();
+        if let Some(id) = self.textures.get(&ptr) {
+            return Some((id.clone(), w as f32, h as f32));
+        }
+        let id = self.id("texture");
+        let _ = writeln!(self.defs,
+            r#"<image id="{}" width="{}" height="{}" preserveAspectRatio="none" xlink:href="data:image/png;base64,{}"/>"#,
+            id, w, h, base64(&png(&inner.image)));
+        self.textures.insert(ptr, id.clone());
+        Some((id, w as f32, h as f32))
+    }
+
+    /// Returns the id of a filter multiplying with a color.
+    fn filter(&mut self, color: Color) -> String {
+        let key = [color[0].to_bits(), color[1].to_bits(), color[2].to_bits(), color[3].to_bits()];
+        if let Some(id) = self.filters.get(&key) {return id.clone()}
+        let id = self.id("tint");
+        let _ = writeln!(self.defs,
+            r#"<filter id="{}"><feColorMatrix type="matrix" values="{} 0 0 0 0 0 {} 0 0 0 0 0 {} 0 0 0 0 0 {} 0"/></filter>"#,
+            id, num(color[0]), num(color[1]), num(color[2]), num(color[3]));
+        self.filters.insert(key, id.clone());
+        id
+    }
+}
+
+/// Returns fill attributes for a color.
+fn fill(color: Color) -> String {
+    paint("fill", color)
+}
+
+/// Returns stroke attributes for a color.
+fn stroke(color: Color) -> String {
+    paint("stroke", color)
+}
+
+/// Returns attributes painting with a color.
+fn paint(attribute: &str, color: Color) -> String {
+    let c = |v: f32| (v.clamp(0.0, 1.0) * 255.0).round() as u8;
+    let mut res = format!(r#"{}="rgb({},{},{})""#, attribute, c(color[0]), c(color[1]), c(color[2]));
+    if color[3] < 1.0 {
+        let _ = write!(res, r#" {}-opacity="{}""#, attribute, num(color[3].max(0.0)));
+    }
+    res
+}
+
+/// Formats a number with limited precision.
+fn num(val: f32) -> String {
+    let s = format!("{:.3}", val);
+    let s = s.trim_end_matches('0').trim_end_matches('.');
+    if s == "-0" {"0".into()} else {s.into()}
+}
+
+/// Encodes an image as PNG.
+fn png(image: &RgbaImage) -> Vec<u8> {
+    let mut res = Cursor::new(vec![]);
+    image.write_to(&mut res, ImageFormat::Png).expect("Could not encode PNG");
+    res.into_inner()
+}
+
+/// Encodes bytes as standard base64 with padding.
+fn base64(bytes: &[u8]) -> String {
+    const TABLE: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut res = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let b = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                res.push(TABLE[(n >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                res.push('=');
+            }
+        }
+    }
+    res
+}
@@ -0,0 +1,191 @@
+//! Tags identifying the commands recorded by a part of an application.
+
+use std::mem;
+use std::sync::OnceLock;
+
+use graphics::{Graphics, ImageSize};
+use range::Range;
+use texture::CreateTexture;
+
+use {Command, DrawError, GraphicsTree, TextureBuffer};
+
+impl GraphicsTree {
+    /// Starts recording commands with a tag, for example the id of a widget.
+    ///
+    /// Tags can be nested, and each `push_tag` must be followed
+    /// by a `pop_tag` when the tagged commands are recorded.
+    /// Tags are recorded as commands, see `CommandRef::PushTag`,
+    /// so draws are never merged or reordered across them.
+    pub fn push_tag(&mut self, tag: u64) {
+        self.push_command(Command::PushTag(tag));
+    }
+
+    /// Ends recording commands with the last pushed tag.
+    pub fn pop_tag(&mut self) {
+        self.push_command(Command::PopTag);
+    }
+
+    /// Records commands with a tag, see `GraphicsTree::push_tag`.
+    pub fn record_tagged<F>(&mut self, tag: u64, f: F)
+        where F: FnOnce(&mut GraphicsTree)
+    {
+        self.push_tag(tag);
+        f(self);
+        self.pop_tag();
+    }
+
+    /// Returns the innermost tag of each command in `GraphicsTree::commands`.
+    ///
+    /// Tag markers have the tag they are recorded in,
+    /// while commands of children are tagged by their `Child` command.
+    pub fn command_tags(&self) -> Vec<Option<u64>> {
+        let mut tags = vec![];
+        let mut res = Vec::with_capacity(self.commands.len());
+        for command in &self.commands {
+            match *command {
+                Command::PushTag(tag) => {
+                    res.push(tags.last().cloned());
+                    tags.push(tag);
+                }
+                Command::PopTag => {
+                    tags.pop();
+                    res.push(tags.last().cloned());
+                }
+                _ => res.push(tags.last().cloned()),
+            }
+        }
+        res
+    }
+
+    /// Removes clears and draws recorded with a tag, including nested tags
+    /// and children added inside the tag.
+    ///
+    /// State changes are kept when used by later draws.
+    /// The vertices, texture coordinates, colors, children and primitives
+    /// of removed commands are dropped, keeping the capacity of the buffers,
+    /// so a tag can be removed and recorded again each frame.
+    /// Children after a removed child are moved,
+    /// so segment handles are no longer valid when a child is removed.
+    pub fn remove_tag(&mut self, tag: u64) {
+        use Command::*;
+
+        for node in &mut self.children {
+            node.tree.remove_tag(tag);
+        }
+
+        let mut tags: Vec<u64> = vec![];
+        let mut kept = vec![];
+        for command in self.take_commands() {
+            let inside = tags.contains(&tag);
+            match command {
+                ChangeColor(_) | ChangeDrawState(_) => {}
+                PushTag(new_tag) => {
+                    tags.push(new_tag);
+                    if inside || new_tag == tag {continue}
+                }
+                PopTag => {
+                    tags.pop();
+                    if inside {continue}
+                }
+                _ => if inside {continue},
+            }
+            kept.push(command);
+        }
+        self.compact(kept);
+        self.remove_redundant_state_changes();
+    }
+
+    /// Records commands again, moving the data they use to the front of the buffers
+    /// and dropping children and primitives that are not used.
+    fn compact(&mut self, commands: Vec<Command>) {
+        use Command::*;
+
+        // The buffers are cleared to keep their capacity.
+        let vertices = self.vertices.clone();
+        let uvs = self.uvs.clone();
+        let colors = self.colors.clone();
+        self.vertices.clear();
+        self.uvs.clear();
+        self.colors.clear();
+        let mut children: Vec<_> = mem::take(&mut self.children).into_iter().map(Some).collect();
+        let mut primitives: Vec<_> = mem::take(&mut self.primitives).into_iter().map(Some).collect();
+        let mut tessellation = mem::take(&mut self.tessellation);
+        for command in commands {
+            let command = match command {
+                Colored(vr) => Colored(move_range(vr, &vertices, &mut self.vertices)),
+                Colors(vr, cr) => Colors(
+                    move_range(vr, &vertices, &mut self.vertices),
+                    move_range(cr, &colors, &mut self.colors),
+                ),
+                Textured(texture, vr, uvr) => Textured(
+                    texture,
+                    move_range(vr, &vertices, &mut self.vertices),
+                    move_range(uvr, &uvs, &mut self.uvs),
+                ),
+                TexturedColor(texture, vr, uvr, cr) => TexturedColor(
+                    texture,
+                    move_range(vr, &vertices, &mut self.vertices),
+                    move_range(uvr, &uvs, &mut self.uvs),
+                    move_range(cr, &colors, &mut self.colors),
+                ),
+                Child(index) => {
+                    self.children.push(children[index].take().unwrap());
+                    Child(self.children.len() - 1)
+                }
+                Primitive(index) => {
+                    self.primitives.push(primitives[index].take().unwrap());
+                    // Keep the triangles computed for the primitive.
+                    self.tessellation.push(tessellation.get_mut(index).map_or_else(OnceLock::new, mem::take));
+                    Primitive(self.primitives.len() - 1)
+                }
+                command => command,
+            };
+            self.push_command(command);
+        }
+    }
+
+    /// Draws the clears and draws recorded with a tag,
+    /// including nested tags and children added inside the tag.
+    ///
+    /// Panics when a texture could not be created or looked up,
+    /// see `GraphicsTree::try_draw`.
+    pub fn draw_tagged<F, T, G>(
+        &self,
+        tag: u64,
+        texture_buffer: &mut TextureBuffer<F, T>,
+        g: &mut G
+    )
+        where
+            T: ImageSize + CreateTexture<F>,
+            G: Graphics<Texture=T>
+    {
+        if let Err(err) = self.try_draw_tagged(tag, texture_buffer, g) {
+            panic!("{}", err);
+        }
+    }
+
+    /// Draws the clears and draws recorded with a tag,
+    /// returning an error when a texture could not be created or looked up.
+    ///
+    /// See `GraphicsTree::draw_tagged` and `GraphicsTree::try_draw`.
+    pub fn try_draw_tagged<F, T, G>(
+        &self,
+        tag: u64,
+        texture_buffer: &mut TextureBuffer<F, T>,
+        g: &mut G
+    ) -> Result<(), DrawError<T::Error>>
+        where
+            T: ImageSize + CreateTexture<F>,
+            G: Graphics<Texture=T>
+    {
+        texture_buffer.collect_garbage();
+        self.draw_transformed(None, None, Some(tag), None, texture_buffer, g)
+    }
+}
+
+/// Copies the data of a range to the end of a buffer, returning the new range.
+fn move_range<T: Copy>(range: Range, from: &[T], to: &mut Vec<T>) -> Range {
+    let offset = to.len();
+    to.extend_from_slice(&from[range.iter()]);
+    Range::new(offset, range.length)
+}
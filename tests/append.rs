@@ -0,0 +1,58 @@
+extern crate graphics;
+extern crate graphics_tree;
+extern crate texture;
+
+mod common;
+
+use std::thread;
+
+use graphics::{DrawState, Graphics};
+use graphics_tree::GraphicsTree;
+use common::{draw, triangle, Call};
+
+const RED: [f32; 4] = [1.0, 0.0, 0.0, 1.0];
+const BLACK: [f32; 4] = [0.0, 0.0, 0.0, 0.0];
+
+#[test]
+fn graphics_tree_is_send() {
+    fn assert_send<T: Send>() {}
+    assert_send::<GraphicsTree>();
+}
+
+#[test]
+fn layers_recorded_on_threads_are_appended_in_order() {
+    let layers: Vec<_> = (0..4).map(|i| thread::spawn(move || {
+        let mut tree = GraphicsTree::new();
+        tree.tri_list(&DrawState::default(), &RED, |f| f(&triangle(i as f32)));
+        tree.add_child("child").tree.tri_list(&DrawState::default(), &RED, |f| {
+            f(&triangle(i as f32 + 0.5))
+        });
+        tree
+    })).collect();
+    let mut tree = GraphicsTree::new();
+    for layer in layers {
+        tree.append(layer.join().unwrap());
+    }
+    let vertices: Vec<Vec<[f32; 2]>> = draw(&tree).calls.into_iter().map(|call| match call {
+        Call::Draw(_, _, _, chunks) => chunks.concat(),
+        call => panic!("unexpected call {:?}", call),
+    }).collect();
+    let expected: Vec<Vec<[f32; 2]>> = (0..8).map(|i| triangle(i as f32 * 0.5).to_vec()).collect();
+    assert_eq!(vertices, expected);
+}
+
+#[test]
+fn state_is_reset_before_appended_commands() {
+    let mut tree = GraphicsTree::new();
+    tree.tri_list(&DrawState::new_clip(), &RED, |f| f(&triangle(0.0)));
+    let mut other = GraphicsTree::new();
+    // Recorded with the default state, so no state change is stored.
+    other.tri_list(&DrawState::default(), &BLACK, |f| f(&triangle(1.0)));
+    tree.append(other);
+    tree.tri_list(&DrawState::new_clip(), &RED, |f| f(&triangle(2.0)));
+    assert_eq!(draw(&tree).calls, [
+        Call::Draw(DrawState::new_clip(), Some(RED), None, vec![triangle(0.0).to_vec()]),
+        Call::Draw(DrawState::default(), Some(BLACK), None, vec![triangle(1.0).to_vec()]),
+        Call::Draw(DrawState::new_clip(), Some(RED), None, vec![triangle(2.0).to_vec()]),
+    ]);
+}
@@ -0,0 +1,72 @@
+extern crate graphics;
+extern crate graphics_tree;
+extern crate texture;
+
+mod common;
+
+use graphics::{DrawState, Graphics};
+use graphics_tree::{Capacity, DoubleBuffer, GraphicsTree};
+use common::draw;
+
+const RED: [f32; 4] = [1.0, 0.0, 0.0, 1.0];
+const TRIANGLE: [[f32; 2]; 3] = [[0.0, 0.0], [0.5, 0.0], [0.0, 0.5]];
+
+const CAPACITY: Capacity = Capacity {commands: 64, vertices: 300, uvs: 30, colors: 300};
+
+fn record(tree: &mut GraphicsTree) {
+    for _ in 0..50 {
+        tree.tri_list(&DrawState::default(), &RED, |f| f(&TRIANGLE));
+    }
+}
+
+#[test]
+fn with_capacity_preallocates() {
+    let tree = GraphicsTree::with_capacity(CAPACITY);
+    let capacity = tree.capacity();
+    assert!(capacity.commands >= 64 && capacity.vertices >= 300);
+    assert!(capacity.uvs >= 30 && capacity.colors >= 300);
+    assert!(tree.memory_usage() > GraphicsTree::new().memory_usage());
+}
+
+#[test]
+fn shrink_to_fit_releases_memory() {
+    let mut tree = GraphicsTree::with_capacity(CAPACITY);
+    record(&mut tree);
+    let before = tree.memory_usage();
+    tree.shrink_to_fit();
+    assert!(tree.memory_usage() < before);
+    assert_eq!(tree.capacity().uvs, 0);
+}
+
+#[test]
+fn swapping_keeps_memory() {
+    let mut buffers = DoubleBuffer::with_capacity(CAPACITY);
+    let mut capacities = vec![];
+    for _ in 0..4 {
+        {
+            let (front, back) = buffers.split();
+            assert!(front.is_empty() || front.capacity() == back.capacity());
+            record(back);
+        }
+        buffers.swap();
+        assert!(buffers.back_mut().is_empty());
+        assert!(!buffers.front().is_empty());
+        capacities.push(buffers.front().capacity());
+    }
+    assert!(capacities.iter().all(|&c| c == capacities[0]));
+}
+
+#[test]
+fn memory_usage_includes_caches() {
+    let mut tree = GraphicsTree::new();
+    tree.set_semantic(true);
+    graphics::rectangle(RED, [0.0, 0.0, 0.5, 0.5], graphics::math::identity(), &mut tree);
+    record(&mut tree);
+    let before = tree.memory_usage();
+    // Drawing caches the merged triangles of primitives.
+    draw(&tree);
+    let drawn = tree.memory_usage();
+    assert!(drawn > before);
+    tree.hit_test([0.1, 0.1]);
+    assert!(tree.memory_usage() > drawn);
+}
@@ -0,0 +1,67 @@
+extern crate graphics;
+extern crate graphics_tree;
+extern crate image;
+extern crate texture;
+
+mod common;
+
+use graphics::{DrawState, Graphics, BACK_END_MAX_VERTEX_COUNT};
+use graphics_tree::{GraphicsTree, Texture, TextureBuffer};
+use image::RgbaImage;
+use common::{Call, Factory, MockTexture, Recorder};
+
+const WHITE: [f32; 4] = [1.0; 4];
+
+/// Records one draw command of each kind with `n` distinct vertices.
+fn record(n: usize, texture: &Texture) -> (GraphicsTree, Vec<[f32; 2]>) {
+    let vertices: Vec<[f32; 2]> = (0..n).map(|i| [i as f32, 0.0]).collect();
+    let colors = vec![WHITE; n];
+    let mut tree = GraphicsTree::new();
+    let draw_state = DrawState::default();
+    tree.tri_list(&draw_state, &WHITE, |f| f(&vertices));
+    tree.tri_list_c(&draw_state, |f| f(&vertices, &colors));
+    tree.tri_list_uv(&draw_state, &WHITE, texture, |f| f(&vertices, &vertices));
+    tree.tri_list_uv_c(&draw_state, texture, |f| f(&vertices, &vertices, &colors));
+    (tree, vertices)
+}
+
+/// Checks the chunks of every draw call against the recorded vertices.
+fn check(n: usize, texture_buffer: &mut TextureBuffer<Factory, MockTexture>, texture: &Texture) {
+    let (tree, vertices) = record(n, texture);
+    let mut recorder = Recorder::default();
+    tree.draw(texture_buffer, &mut recorder);
+    assert_eq!(recorder.calls.len(), 4);
+    for call in &recorder.calls {
+        let chunks = match *call {
+            Call::Draw(_, _, _, ref chunks) => chunks,
+            ref call => panic!("unexpected call {:?}", call),
+        };
+        for chunk in chunks {
+            assert!(!chunk.is_empty(), "empty chunk for {} vertices", n);
+            assert_eq!(chunk.len() % 3, 0, "split triangle for {} vertices", n);
+            assert!(chunk.len() <= BACK_END_MAX_VERTEX_COUNT, "large chunk for {} vertices", n);
+        }
+        assert_eq!(chunks.concat(), &vertices[..n - n % 3]);
+    }
+}
+
+#[test]
+fn chunks_hold_whole_triangles_within_backend_limit() {
+    let texture = Texture::from(RgbaImage::new(1, 1));
+    let mut texture_buffer = TextureBuffer::new(Factory::default());
+    for n in 0..3 * BACK_END_MAX_VERTEX_COUNT + 7 {
+        check(n, &mut texture_buffer, &texture);
+    }
+}
+
+#[test]
+fn chunks_split_large_draws() {
+    let texture = Texture::from(RgbaImage::new(1, 1));
+    let mut texture_buffer = TextureBuffer::new(Factory::default());
+    // A simple linear congruential generator for varied lengths.
+    let mut seed: u64 = 17;
+    for _ in 0..20 {
+        seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        check((seed >> 33) as usize % 50_000, &mut texture_buffer, &texture);
+    }
+}
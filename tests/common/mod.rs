@@ -0,0 +1,176 @@
+//! A graphics backend that records calls, for testing without a GPU.
+
+#![allow(dead_code)]
+
+use graphics::{DrawState, Graphics, ImageSize};
+use graphics::types::Color;
+use graphics_tree::{GraphicsTree, TextureBuffer};
+use texture::{CreateTexture, Format, TextureOp, TextureSettings, UpdateTexture};
+
+/// Creates mock textures.
+#[derive(Default)]
+pub struct Factory {
+    /// The number of created textures.
+    pub created: usize,
+    /// The offset, size and memory length of updates.
+    pub updated: Vec<([u32; 2], [u32; 2], usize)>,
+    /// Whether creating textures fails.
+    pub fail: bool,
+    /// The settings of created textures.
+    pub settings: Vec<TextureSettings>,
+}
+
+/// A texture created by `Factory`.
+pub struct MockTexture {
+    /// The creation order of the texture.
+    pub id: usize,
+    pub size: [u32; 2],
+}
+
+impl ImageSize for MockTexture {
+    fn get_size(&self) -> (u32, u32) {
+        (self.size[0], self.size[1])
+    }
+}
+
+impl TextureOp<Factory> for MockTexture {
+    type Error = ();
+}
+
+impl CreateTexture<Factory> for MockTexture {
+    fn create<S: Into<[u32; 2]>>(
+        factory: &mut Factory,
+        _format: Format,
+        memory: &[u8],
+        size: S,
+        settings: &TextureSettings
+    ) -> Result<Self, ()> {
+        let size = size.into();
+        assert_eq!(memory.len(), size[0] as usize * size[1] as usize * 4, "texture memory does not match size");
+        if factory.fail {return Err(())}
+        factory.created += 1;
+        factory.settings.push(*settings);
+        Ok(MockTexture {id: factory.created, size})
+    }
+}
+
+impl UpdateTexture<Factory> for MockTexture {
+    fn update<O, S>(
+        &mut self,
+        factory: &mut Factory,
+        _format: Format,
+        memory: &[u8],
+        offset: O,
+        size: S
+    ) -> Result<(), ()>
+        where O: Into<[u32; 2]>, S: Into<[u32; 2]>
+    {
+        factory.updated.push((offset.into(), size.into(), memory.len()));
+        Ok(())
+    }
+}
+
+/// A call to the backend.
+#[derive(Debug, PartialEq)]
+pub enum Call {
+    ClearColor(Color),
+    ClearStencil(u8),
+    /// Draw state, color, texture id and vertex chunks.
+    Draw(DrawState, Option<Color>, Option<usize>, Vec<Vec<[f32; 2]>>),
+}
+
+/// Records calls to the backend.
+#[derive(Default)]
+pub struct Recorder {
+    pub calls: Vec<Call>,
+}
+
+impl Recorder {
+    /// Returns the vertices of all draw calls.
+    pub fn vertices(&self) -> Vec<[f32; 2]> {
+        self.calls.iter().flat_map(|call| match *call {
+            Call::Draw(_, _, _, ref chunks) => chunks.concat(),
+            _ => vec![],
+        }).collect()
+    }
+}
+
+/// Returns a small triangle with its corner at `[x, 0.0]`.
+pub fn triangle(x: f32) -> [[f32; 2]; 3] {
+    [[x, 0.0], [x + 0.1, 0.0], [x, 0.1]]
+}
+
+/// Draws a tree with a new texture buffer, recording the calls.
+pub fn draw(tree: &GraphicsTree) -> Recorder {
+    let mut recorder = Recorder::default();
+    tree.draw(&mut TextureBuffer::new(Factory::default()), &mut recorder);
+    recorder
+}
+
+/// Draws the part of a tree inside a viewport, recording the calls.
+pub fn draw_with_viewport(tree: &GraphicsTree, viewport: [f32; 4]) -> Recorder {
+    let mut recorder = Recorder::default();
+    tree.draw_with_viewport(viewport, &mut TextureBuffer::new(Factory::default()), &mut recorder);
+    recorder
+}
+
+impl Graphics for Recorder {
+    type Texture = MockTexture;
+
+    fn clear_color(&mut self, color: Color) {
+        self.calls.push(Call::ClearColor(color));
+    }
+
+    fn clear_stencil(&mut self, value: u8) {
+        self.calls.push(Call::ClearStencil(value));
+    }
+
+    fn tri_list<F>(&mut self, draw_state: &DrawState, color: &Color, mut f: F)
+        where F: FnMut(&mut dyn FnMut(&[[f32; 2]]))
+    {
+        let mut chunks = vec![];
+        f(&mut |v| chunks.push(v.to_vec()));
+        self.calls.push(Call::Draw(*draw_state, Some(*color), None, chunks));
+    }
+
+    fn tri_list_c<F>(&mut self, draw_state: &DrawState, mut f: F)
+        where F: FnMut(&mut dyn FnMut(&[[f32; 2]], &[[f32; 4]]))
+    {
+        let mut chunks = vec![];
+        f(&mut |v, c| {
+            assert_eq!(v.len(), c.len());
+            chunks.push(v.to_vec());
+        });
+        self.calls.push(Call::Draw(*draw_state, None, None, chunks));
+    }
+
+    fn tri_list_uv<F>(
+        &mut self,
+        draw_state: &DrawState,
+        color: &Color,
+        texture: &MockTexture,
+        mut f: F
+    ) where F: FnMut(&mut dyn FnMut(&[[f32; 2]], &[[f32; 2]])) {
+        let mut chunks = vec![];
+        f(&mut |v, uv| {
+            assert_eq!(v.len(), uv.len());
+            chunks.push(v.to_vec());
+        });
+        self.calls.push(Call::Draw(*draw_state, Some(*color), Some(texture.id), chunks));
+    }
+
+    fn tri_list_uv_c<F>(
+        &mut self,
+        draw_state: &DrawState,
+        texture: &MockTexture,
+        mut f: F
+    ) where F: FnMut(&mut dyn FnMut(&[[f32; 2]], &[[f32; 2]], &[[f32; 4]])) {
+        let mut chunks = vec![];
+        f(&mut |v, uv, c| {
+            assert_eq!(v.len(), uv.len());
+            assert_eq!(v.len(), c.len());
+            chunks.push(v.to_vec());
+        });
+        self.calls.push(Call::Draw(*draw_state, None, Some(texture.id), chunks));
+    }
+}
@@ -0,0 +1,141 @@
+extern crate graphics;
+extern crate graphics_tree;
+extern crate image;
+extern crate texture;
+#[cfg(feature = "serde")]
+extern crate serde_json;
+
+use graphics::{DrawState, Graphics};
+use graphics::math::identity;
+use graphics_tree::{CommandData, CommandRef, GraphicsTree, LoadError, Texture};
+use texture::{Filter, TextureSettings, Wrap};
+
+fn record() -> (GraphicsTree, Texture) {
+    let mut tree = GraphicsTree::new();
+    let texture: Texture = image::RgbaImage::from_pixel(2, 2, image::Rgba([1, 2, 3, 4])).into();
+    // Same content, different handle.
+    let copy: Texture = image::RgbaImage::from_pixel(2, 2, image::Rgba([1, 2, 3, 4])).into();
+    tree.clear_color([1.0; 4]);
+    tree.clear_stencil(0);
+    graphics::rectangle([1.0, 0.0, 0.0, 1.0], [0.0, 0.0, 1.0, 1.0], identity(), &mut tree);
+    tree.tri_list_c(&DrawState::new_clip().scissor([1, 2, 3, 4]), |f| {
+        f(&[[0.0; 2]; 3], &[[0.5; 4]; 3])
+    });
+    graphics::image(&texture, identity(), &mut tree);
+    graphics::image(&copy, identity(), &mut tree);
+    tree.tri_list_uv_c(&DrawState::default(), &texture, |f| {
+        f(&[[0.0; 2]; 3], &[[1.0; 2]; 3], &[[0.25; 4]; 3])
+    });
+    (tree, texture)
+}
+
+#[test]
+fn binary_round_trip() {
+    let (mut tree, _) = record();
+    let settings = TextureSettings::new().filter(Filter::Nearest).wrap_u(Wrap::Repeat);
+    let nearest = Texture::with_settings(image::RgbaImage::from_pixel(2, 2, image::Rgba([1, 2, 3, 4])), settings);
+    graphics::image(&nearest, identity(), &mut tree);
+    let bytes = tree.to_bytes();
+    let loaded = GraphicsTree::from_bytes(&bytes).unwrap();
+    assert_eq!(tree.to_dump(), loaded.to_dump());
+    assert_eq!(loaded.to_bytes(), bytes);
+
+    // The same image with other settings is stored separately.
+    assert_eq!(loaded.to_dump().textures.len(), 2);
+    let settings: Vec<TextureSettings> = loaded.commands().filter_map(|command| match command {
+        CommandRef::Textured {texture, ..} => Some(texture.settings()),
+        _ => None,
+    }).collect();
+    assert!(matches!(settings[0].get_mag(), Filter::Linear));
+    let last = settings.last().unwrap();
+    assert!(matches!(last.get_min(), Filter::Nearest));
+    assert!(matches!(last.get_mag(), Filter::Nearest));
+    assert_eq!(last.get_wrap_u(), Wrap::Repeat);
+    assert_eq!(last.get_wrap_v(), Wrap::ClampToEdge);
+}
+
+#[test]
+fn textures_are_deduplicated() {
+    let (tree, texture) = record();
+    let dump = tree.to_dump();
+    assert_eq!(dump.textures.len(), 1);
+    assert_eq!(dump.textures[0].hash, graphics_tree::content_hash(&texture.0.read().unwrap().image));
+    let hashes: Vec<u64> = dump.commands.iter().filter_map(|command| match *command {
+        CommandData::Textured {texture, ..} |
+        CommandData::TexturedColor {texture, ..} => Some(texture),
+        _ => None,
+    }).collect();
+    assert_eq!(hashes.len(), 3);
+    assert!(hashes.iter().all(|&hash| hash == dump.textures[0].hash));
+}
+
+#[test]
+fn invalid_data_is_rejected() {
+    let (tree, _) = record();
+    let bytes = tree.to_bytes();
+    assert_eq!(GraphicsTree::from_bytes(&bytes[1..]).err(), Some(LoadError::InvalidHeader));
+    assert_eq!(GraphicsTree::from_bytes(&bytes[..bytes.len() - 1]).err(),
+               Some(LoadError::UnexpectedEnd));
+
+    let mut dump = tree.to_dump();
+    dump.vertices.pop();
+    assert_eq!(GraphicsTree::from_dump(dump).err(), Some(LoadError::InvalidRange));
+
+    let mut dump = tree.to_dump();
+    dump.textures[0].pixels.push(0);
+    let hash = dump.textures[0].hash;
+    assert_eq!(GraphicsTree::from_dump(dump).err(), Some(LoadError::InvalidTexture(hash)));
+
+    let mut dump = tree.to_dump();
+    let hash = dump.textures.pop().unwrap().hash;
+    assert_eq!(GraphicsTree::from_dump(dump).err(), Some(LoadError::MissingTexture(hash)));
+}
+
+#[test]
+fn mismatched_ranges_are_rejected() {
+    let (tree, _) = record();
+    let dump = tree.to_dump();
+    let load = |command: CommandData| {
+        let mut dump = dump.clone();
+        dump.commands = vec![command];
+        GraphicsTree::from_dump(dump).err()
+    };
+    assert_eq!(load(CommandData::Colors {vertices: [0, 3], colors: [0, 3]}), None);
+    assert_eq!(load(CommandData::Colors {vertices: [0, 3], colors: [0, 1]}),
+               Some(LoadError::InvalidRange));
+    assert_eq!(load(CommandData::Colored {vertices: [0, 4]}), None);
+    let texture = dump.textures[0].hash;
+    assert_eq!(load(CommandData::Textured {texture, vertices: [0, 6], uvs: [0, 3]}),
+               Some(LoadError::InvalidRange));
+    assert_eq!(load(CommandData::TexturedColor {
+        texture,
+        vertices: [0, 3],
+        uvs: [0, 3],
+        colors: [0, 6],
+    }), Some(LoadError::InvalidRange));
+}
+
+#[test]
+fn partial_triangles_round_trip() {
+    let mut tree = GraphicsTree::new();
+    tree.tri_list(&DrawState::default(), &[1.0; 4], |f| {
+        f(&[[0.0, 0.0], [1.0, 0.0], [0.0, 1.0], [1.0, 1.0]])
+    });
+    let bytes = tree.to_bytes();
+    let loaded = GraphicsTree::from_bytes(&bytes).unwrap();
+    assert_eq!(loaded.to_bytes(), bytes);
+    match loaded.commands().last() {
+        Some(CommandRef::Colored {vertices}) => assert_eq!(vertices.len(), 4),
+        command => panic!("unexpected command {:?}", command),
+    }
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn json_round_trip() {
+    let (tree, _) = record();
+    let json = serde_json::to_string(&tree.to_dump()).unwrap();
+    let dump = serde_json::from_str(&json).unwrap();
+    assert_eq!(tree.to_dump(), dump);
+    GraphicsTree::from_dump(dump).unwrap();
+}
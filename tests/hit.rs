@@ -0,0 +1,111 @@
+extern crate graphics;
+extern crate graphics_tree;
+extern crate image;
+
+use graphics::math::{identity, translate};
+use graphics::{DrawState, Graphics};
+use graphics_tree::{GraphicsTree, Hit, Texture};
+use image::{Rgba, RgbaImage};
+
+const RED: [f32; 4] = [1.0, 0.0, 0.0, 1.0];
+const BLUE: [f32; 4] = [0.0, 0.0, 1.0, 1.0];
+
+fn hit(command: usize) -> Option<Hit> {
+    Some(Hit {path: vec![], command, tag: None})
+}
+
+#[test]
+fn topmost_command_is_hit() {
+    let mut tree = GraphicsTree::new();
+    graphics::rectangle(RED, [-0.5, -0.5, 1.0, 1.0], identity(), &mut tree);
+    graphics::rectangle(BLUE, [0.0, 0.0, 1.0, 1.0], identity(), &mut tree);
+    // Each rectangle follows a color change.
+    assert_eq!(tree.hit_test([0.25, 0.25]), hit(3));
+    assert_eq!(tree.hit_test([-0.25, -0.25]), hit(1));
+    assert_eq!(tree.hit_test([0.75, -0.75]), None);
+
+    graphics::rectangle(RED, [-1.0, -1.0, 2.0, 2.0], identity(), &mut tree);
+    assert_eq!(tree.hit_test([0.75, -0.75]), hit(5));
+}
+
+#[test]
+fn draws_before_clear_are_not_hit() {
+    let mut tree = GraphicsTree::new();
+    graphics::rectangle(RED, [-1.0, -1.0, 2.0, 2.0], identity(), &mut tree);
+    {
+        let node = tree.add_child("child");
+        graphics::rectangle(RED, [-1.0, -1.0, 2.0, 2.0], identity(), &mut node.tree);
+    }
+    tree.clear_color(BLUE);
+    graphics::rectangle(BLUE, [0.0, 0.0, 1.0, 1.0], identity(), &mut tree);
+    assert_eq!(tree.hit_test([-0.5, -0.5]), None);
+    assert_eq!(tree.hit_test([0.5, 0.5]), hit(5));
+}
+
+#[test]
+fn many_triangles_are_indexed() {
+    let mut tree = GraphicsTree::new();
+    for i in 0..100 {
+        let x = i as f32 * 0.02 - 1.0;
+        tree.tri_list(&DrawState::default(), &RED, |f| f(&[[x, 0.0], [x + 0.02, 0.0], [x, 0.02]]));
+    }
+    for i in 0..100 {
+        let x = i as f32 * 0.02 - 1.0;
+        // The first draw changes the color.
+        let command = if i == 0 {1} else {i + 1};
+        assert_eq!(tree.hit_test([x + 0.005, 0.005]), hit(command));
+    }
+    assert_eq!(tree.hit_test([0.5, 0.5]), None);
+}
+
+#[test]
+fn children_are_hit_with_transform() {
+    let mut tree = GraphicsTree::new();
+    graphics::rectangle(RED, [-1.0, -1.0, 1.0, 1.0], identity(), &mut tree);
+    {
+        let node = tree.add_child("child");
+        graphics::rectangle(BLUE, [0.0, 0.0, 0.5, 0.5], identity(), &mut node.tree);
+        node.transform = translate([-0.75, -0.75]);
+    }
+    assert_eq!(tree.hit_test([-0.5, -0.5]), Some(Hit {path: vec![0], command: 1, tag: None}));
+    assert_eq!(tree.hit_test([0.25, 0.25]), None);
+
+    tree.child_mut("child").unwrap().visible = false;
+    assert_eq!(tree.hit_test([-0.5, -0.5]), hit(1));
+
+    tree.child_mut("child").unwrap().visible = true;
+    graphics::rectangle(RED, [-1.0, -1.0, 2.0, 2.0], identity(), &mut tree);
+    assert_eq!(tree.hit_test([-0.5, -0.5]), hit(3));
+}
+
+#[test]
+fn stencil_draws_are_ignored() {
+    let mut tree = GraphicsTree::new();
+    graphics::rectangle(RED, [-1.0, -1.0, 2.0, 2.0], identity(), &mut tree);
+    tree.tri_list(&DrawState::new_clip(), &RED, |f| f(&[[0.0, 0.0], [1.0, 0.0], [0.0, 1.0]]));
+    assert_eq!(tree.hit_test([0.1, 0.1]), hit(1));
+}
+
+#[test]
+fn transparent_texels_are_ignored_with_alpha() {
+    let mut image = RgbaImage::new(2, 1);
+    image.put_pixel(1, 0, Rgba([255; 4]));
+    let texture = Texture::from(image);
+    let mut tree = GraphicsTree::new();
+    graphics::rectangle(RED, [-1.0, -1.0, 2.0, 2.0], identity(), &mut tree);
+    graphics::Image::new().rect([-1.0, -1.0, 2.0, 2.0])
+        .draw(&texture, &DrawState::default(), identity(), &mut tree);
+    assert_eq!(tree.hit_test([-0.5, 0.0]), hit(3));
+    assert_eq!(tree.hit_test_with_alpha([-0.5, 0.0], 128), hit(1));
+    assert_eq!(tree.hit_test_with_alpha([0.5, 0.0], 128), hit(3));
+}
+
+#[test]
+fn primitives_are_hit() {
+    let mut tree = GraphicsTree::new();
+    tree.set_semantic(true);
+    graphics::ellipse(RED, [-1.0, -1.0, 2.0, 2.0], identity(), &mut tree);
+    assert_eq!(tree.hit_test([0.0, 0.0]), hit(0));
+    assert_eq!(tree.hit_test([0.95, 0.95]), None);
+}
+
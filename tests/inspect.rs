@@ -0,0 +1,56 @@
+extern crate graphics;
+extern crate graphics_tree;
+extern crate image;
+
+use graphics::{DrawState, Graphics};
+use graphics_tree::{CommandRef, GraphicsTree, Texture};
+
+#[test]
+fn commands_expose_recorded_data() {
+    let mut tree = GraphicsTree::new();
+    let texture: Texture = image::RgbaImage::new(2, 2).into();
+    let draw_state = DrawState::new_clip();
+    let vertices = [[0.0, 0.0], [1.0, 0.0], [0.0, 1.0]];
+    let uvs = [[0.0, 0.0], [1.0, 0.0], [0.0, 1.0]];
+    let colors = [[1.0, 0.0, 0.0, 1.0], [0.0, 1.0, 0.0, 1.0], [0.0, 0.0, 1.0, 1.0]];
+
+    tree.clear_color([1.0; 4]);
+    tree.clear_stencil(0);
+    tree.tri_list(&draw_state, &[0.5; 4], |f| f(&vertices));
+    tree.tri_list_c(&draw_state, |f| f(&vertices, &colors));
+    tree.tri_list_uv(&draw_state, &[0.5; 4], &texture, |f| f(&vertices, &uvs));
+    tree.tri_list_uv_c(&draw_state, &texture, |f| f(&vertices, &uvs, &colors));
+
+    let commands: Vec<_> = tree.commands().collect();
+    assert_eq!(commands, [
+        CommandRef::ClearColor([1.0; 4]),
+        CommandRef::ClearStencil(0),
+        CommandRef::ChangeColor([0.5; 4]),
+        CommandRef::ChangeDrawState(draw_state),
+        CommandRef::Colored {vertices: &vertices},
+        CommandRef::Colors {vertices: &vertices, colors: &colors},
+        CommandRef::Textured {texture: &texture, vertices: &vertices, uvs: &uvs},
+        CommandRef::TexturedColor {
+            texture: &texture,
+            vertices: &vertices,
+            uvs: &uvs,
+            colors: &colors
+        },
+    ]);
+    assert_eq!(tree.commands().len(), 8);
+
+    // Pushing the commands records them again.
+    let mut rebuilt = GraphicsTree::new();
+    for command in tree.commands() {
+        rebuilt.push(command);
+    }
+    assert_eq!(rebuilt.commands().collect::<Vec<_>>(), commands);
+}
+
+#[test]
+fn textures_compare_by_identity() {
+    let a: Texture = image::RgbaImage::new(2, 2).into();
+    let b: Texture = image::RgbaImage::new(2, 2).into();
+    assert_eq!(a, a.clone());
+    assert!(a != b);
+}
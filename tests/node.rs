@@ -0,0 +1,88 @@
+extern crate graphics;
+extern crate graphics_tree;
+extern crate texture;
+
+mod common;
+
+use graphics::{DrawState, Graphics};
+use graphics_tree::{CommandRef, GraphicsTree};
+use common::{draw, Call};
+
+const RED: [f32; 4] = [1.0, 0.0, 0.0, 1.0];
+const BLUE: [f32; 4] = [0.0, 0.0, 1.0, 1.0];
+const TRIANGLE: [[f32; 2]; 3] = [[0.0, 0.0], [0.5, 0.0], [0.0, 0.5]];
+
+fn record() -> GraphicsTree {
+    let mut tree = GraphicsTree::new();
+    tree.tri_list(&DrawState::default(), &RED, |f| f(&TRIANGLE));
+    {
+        let node = tree.add_child("child");
+        node.transform = [[1.0, 0.0, 0.5], [0.0, 1.0, 0.0]];
+        node.tree.tri_list(&DrawState::new_alpha(), &BLUE, |f| f(&TRIANGLE));
+    }
+    tree.tri_list(&DrawState::default(), &RED, |f| f(&TRIANGLE));
+    tree
+}
+
+#[test]
+fn children_are_drawn_in_order_with_transform() {
+    let tree = record();
+    let recorder = draw(&tree);
+    let moved = vec![[0.5, 0.0], [1.0, 0.0], [0.5, 0.5]];
+    assert_eq!(recorder.calls, [
+        Call::Draw(DrawState::default(), Some(RED), None, vec![TRIANGLE.to_vec()]),
+        Call::Draw(DrawState::default(), Some(BLUE), None, vec![moved]),
+        Call::Draw(DrawState::default(), Some(RED), None, vec![TRIANGLE.to_vec()]),
+    ]);
+    match tree.commands().nth(2) {
+        Some(CommandRef::Child(node)) => assert_eq!(node.name(), "child"),
+        _ => panic!("Expected child"),
+    }
+}
+
+#[test]
+fn hidden_children_are_not_drawn() {
+    let mut tree = record();
+    tree.child_mut("child").unwrap().visible = false;
+    assert_eq!(draw(&tree).calls.len(), 2);
+}
+
+#[test]
+fn children_are_recorded_separately() {
+    let mut tree = record();
+    {
+        let node = tree.child_mut("child").unwrap();
+        node.tree.clear();
+        node.tree.tri_list(&DrawState::default(), &RED, |f| f(&TRIANGLE));
+        node.tree.tri_list(&DrawState::default(), &RED, |f| f(&TRIANGLE));
+    }
+    // Adding an existing child returns it without recording it again.
+    tree.add_child("child");
+    assert_eq!(tree.children().len(), 1);
+    assert_eq!(draw(&tree).calls.len(), 4);
+}
+
+#[test]
+fn nested_transforms_are_composed() {
+    let mut tree = GraphicsTree::new();
+    {
+        let a = tree.add_child("a");
+        a.transform = [[2.0, 0.0, 0.0], [0.0, 2.0, 0.0]];
+        let b = a.tree.add_child("b");
+        b.transform = [[1.0, 0.0, 0.25], [0.0, 1.0, 0.0]];
+        b.tree.tri_list(&DrawState::default(), &RED, |f| f(&TRIANGLE));
+    }
+    assert_eq!(draw(&tree).vertices(), [[0.5, 0.0], [1.5, 0.0], [0.5, 1.0]]);
+}
+
+#[test]
+fn flatten_restores_state_after_children() {
+    let tree = record();
+    let flat = tree.flatten();
+    assert!(flat.children().is_empty());
+    let commands: Vec<_> = flat.commands().collect();
+    assert_eq!(commands.len(), 6);
+    assert_eq!(commands[2], CommandRef::ChangeColor(BLUE));
+    assert_eq!(commands[4], CommandRef::ChangeColor(RED));
+    assert_eq!(draw(&flat).calls, draw(&tree).calls);
+}
@@ -0,0 +1,75 @@
+extern crate graphics;
+extern crate graphics_tree;
+extern crate texture;
+
+mod common;
+
+use graphics::draw_state::Stencil;
+use graphics::math::identity;
+use graphics::{DrawState, Graphics};
+use graphics_tree::GraphicsTree;
+use common::{draw, Call};
+
+const RED: [f32; 4] = [1.0, 0.0, 0.0, 1.0];
+const BLUE: [f32; 4] = [0.0, 0.0, 1.0, 1.0];
+const GLASS: [f32; 4] = [0.0, 0.0, 1.0, 0.5];
+const SMALL: [f64; 4] = [-0.5, -0.5, 0.5, 0.5];
+const LARGE: [f64; 4] = [-1.0, -1.0, 2.0, 2.0];
+
+fn colors(calls: &[Call]) -> Vec<Option<[f32; 4]>> {
+    calls.iter().map(|call| match *call {
+        Call::Draw(_, color, _, _) => color,
+        _ => None,
+    }).collect()
+}
+
+#[test]
+fn covered_draws_are_removed() {
+    let mut tree = GraphicsTree::new();
+    graphics::rectangle(RED, SMALL, identity(), &mut tree);
+    graphics::rectangle(BLUE, LARGE, identity(), &mut tree);
+    tree.remove_occluded();
+    assert_eq!(colors(&draw(&tree).calls), [Some(BLUE)]);
+}
+
+#[test]
+fn transparent_and_partial_covers_keep_draws() {
+    let mut tree = GraphicsTree::new();
+    graphics::rectangle(RED, LARGE, identity(), &mut tree);
+    graphics::rectangle(GLASS, LARGE, identity(), &mut tree);
+    graphics::rectangle(BLUE, SMALL, identity(), &mut tree);
+    tree.remove_occluded();
+    assert_eq!(colors(&draw(&tree).calls), [Some(RED), Some(GLASS), Some(BLUE)]);
+}
+
+#[test]
+fn draws_before_clear_are_removed_except_stencil() {
+    let mut tree = GraphicsTree::new();
+    let clip = DrawState::new_clip();
+    graphics::rectangle(RED, SMALL, identity(), &mut tree);
+    tree.tri_list(&clip, &BLUE, |f| f(&[[0.0, 0.0], [0.5, 0.0], [0.0, 0.5]]));
+    tree.clear_color(BLUE);
+    tree.clear_color(RED);
+    tree.remove_occluded();
+    let calls = draw(&tree).calls;
+    assert_eq!(calls.len(), 2);
+    match calls[0] {
+        Call::Draw(draw_state, _, _, _) => assert_eq!(draw_state.stencil, Some(Stencil::Clip(255))),
+        ref call => panic!("unexpected call {:?}", call),
+    }
+    assert_eq!(calls[1], Call::ClearColor(RED));
+}
+
+#[test]
+fn clears_only_remove_draws_with_the_same_tags() {
+    let mut tree = GraphicsTree::new();
+    tree.record_tagged(1, |tree| graphics::rectangle(RED, SMALL, identity(), tree));
+    tree.record_tagged(2, |tree| {
+        graphics::rectangle(BLUE, SMALL, identity(), tree);
+        tree.clear_color(BLUE);
+    });
+    tree.remove_occluded();
+    let calls = draw(&tree).calls;
+    assert_eq!(colors(&calls), [Some(RED), None]);
+    assert_eq!(calls[1], Call::ClearColor(BLUE));
+}
@@ -0,0 +1,90 @@
+extern crate graphics;
+extern crate graphics_tree;
+extern crate image;
+extern crate texture;
+
+mod common;
+
+use graphics::math::identity;
+use graphics::Image;
+use graphics_tree::{CommandRef, GraphicsTree, Texture};
+use common::draw;
+
+fn texture() -> Texture {
+    image::RgbaImage::from_pixel(2, 2, image::Rgba([255; 4])).into()
+}
+
+fn image(texture: &Texture, x: f64, tree: &mut GraphicsTree) {
+    Image::new().rect([x, 0.0, 0.25, 0.25]).draw(texture, &Default::default(), identity(), tree);
+}
+
+/// Returns the number of vertices of each textured draw.
+fn textured(tree: &GraphicsTree) -> Vec<usize> {
+    tree.commands().filter_map(|command| match command {
+        CommandRef::Textured {vertices, uvs, ..} => {
+            assert_eq!(vertices.len(), uvs.len());
+            Some(vertices.len())
+        }
+        _ => None,
+    }).collect()
+}
+
+#[test]
+fn adjacent_draws_with_same_texture_are_merged() {
+    let mut tree = GraphicsTree::new();
+    let texture = texture();
+    image(&texture, -1.0, &mut tree);
+    image(&texture, 0.0, &mut tree);
+    image(&texture, 0.5, &mut tree);
+    tree.optimize();
+    assert_eq!(draw(&tree).calls.len(), 1);
+    assert_eq!(textured(&tree), [18]);
+}
+
+#[test]
+fn draws_with_different_textures_are_not_merged() {
+    let mut tree = GraphicsTree::new();
+    // Same content, different handles.
+    let (a, b) = (texture(), texture());
+    image(&a, -1.0, &mut tree);
+    image(&b, 0.0, &mut tree);
+    image(&b, 0.5, &mut tree);
+    tree.optimize();
+    assert_eq!(draw(&tree).calls.len(), 2);
+    assert_eq!(textured(&tree), [6, 12]);
+}
+
+#[test]
+fn draws_with_non_adjacent_ranges_are_not_merged() {
+    let mut tree = GraphicsTree::new();
+    let texture = texture();
+    image(&texture, -1.0, &mut tree);
+    graphics::rectangle([1.0, 0.0, 0.0, 1.0], [0.8, -0.9, 0.05, 0.05], identity(), &mut tree);
+    image(&texture, 0.5, &mut tree);
+    graphics::rectangle([0.0, 0.0, 1.0, 1.0], [0.7, -1.0, 0.3, 0.3], identity(), &mut tree);
+    // Leaves a gap in the vertices between the images.
+    tree.remove_occluded();
+    tree.optimize();
+    assert_eq!(draw(&tree).calls.len(), 3);
+    assert_eq!(textured(&tree), [6, 6]);
+}
+
+#[cfg(feature = "software")]
+#[test]
+fn draws_with_partial_triangles_are_not_merged() {
+    use graphics::Graphics;
+
+    let mut tree = GraphicsTree::new();
+    let red = [1.0, 0.0, 0.0, 1.0];
+    // The fourth vertex is not drawn.
+    tree.tri_list(&Default::default(), &red, |f| {
+        f(&[[-1.0, -1.0], [0.0, -1.0], [-1.0, 0.0], [1.0, 1.0]])
+    });
+    tree.tri_list(&Default::default(), &red, |f| {
+        f(&[[1.0, -1.0], [0.0, 1.0], [0.5, 0.0]])
+    });
+    let expected = tree.rasterize(8, 8);
+    tree.optimize();
+    assert_eq!(tree.rasterize(8, 8), expected);
+    assert_eq!(tree.commands().filter(|c| matches!(c, CommandRef::Colored {..})).count(), 2);
+}
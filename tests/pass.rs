@@ -0,0 +1,128 @@
+extern crate graphics;
+extern crate graphics_tree;
+
+use std::mem;
+use std::sync::{Arc, Mutex};
+
+use graphics::Graphics;
+use graphics::math::identity;
+use graphics_tree::{CommandRef, GraphicsTree, Optimize, Pass, Pipeline, PromoteColors};
+
+const RED: [f32; 4] = [1.0, 0.0, 0.0, 1.0];
+const BLUE: [f32; 4] = [0.0, 0.0, 1.0, 1.0];
+
+fn scene() -> GraphicsTree {
+    let mut tree = GraphicsTree::new();
+    graphics::rectangle(RED, [-0.5, -0.5, 0.5, 0.5], identity(), &mut tree);
+    graphics::rectangle(BLUE, [0.2, 0.2, 0.5, 0.5], identity(), &mut tree);
+    graphics::rectangle(RED, [-1.0, 0.5, 0.4, 0.4], identity(), &mut tree);
+    graphics::rectangle(BLUE, [-1.0, -1.0, 2.0, 2.0], identity(), &mut tree);
+    graphics::rectangle(RED, [-0.9, -0.9, 0.2, 0.2], identity(), &mut tree);
+    tree
+}
+
+#[test]
+fn default_pipeline() {
+    let pipeline = Pipeline::default();
+    assert_eq!(pipeline.names(), ["remove_occluded", "reorder"]);
+    let mut tree = scene();
+    let mut pipeline = pipeline;
+    pipeline.run(&mut tree);
+    assert_eq!(tree.stats().draws(), 2);
+}
+
+#[test]
+fn custom_passes_run_in_order() {
+    /// Logs its name and the number of draws when run.
+    struct Log {
+        name: &'static str,
+        log: Arc<Mutex<Vec<(&'static str, usize)>>>,
+    }
+
+    impl Pass for Log {
+        fn name(&self) -> &str {self.name}
+        fn run(&mut self, tree: &mut GraphicsTree) {
+            self.log.lock().unwrap().push((self.name, tree.stats().draws()));
+        }
+    }
+
+    let log = Arc::new(Mutex::new(vec![]));
+    let logged = |name| Log {name, log: log.clone()};
+    let mut pipeline = Pipeline::new();
+    pipeline.add_pass(logged("first"));
+    pipeline.add_pass(PromoteColors);
+    pipeline.add_pass(logged("promoted"));
+    pipeline.add_pass(|tree: &mut GraphicsTree| tree.clear_color(RED));
+    pipeline.add_pass(Optimize);
+    pipeline.add_pass(logged("last"));
+    assert_eq!(pipeline.names()[..3], ["first", "promote_colors", "promoted"]);
+    assert_eq!(pipeline.names()[4..], ["optimize", "last"]);
+    let mut tree = scene();
+    pipeline.run(&mut tree);
+    assert_eq!(*log.lock().unwrap(), [("first", 5), ("promoted", 1), ("last", 1)]);
+    assert_eq!(tree.stats().clear_color, 1);
+}
+
+/// Rewrites all colors to blue.
+fn paint_blue(tree: &mut GraphicsTree) {
+    let recorded = mem::take(tree);
+    for command in recorded.commands() {
+        match command {
+            CommandRef::ChangeColor(_) => tree.push(CommandRef::ChangeColor(BLUE)),
+            command => tree.push(command),
+        }
+    }
+}
+
+#[test]
+fn custom_passes_rewrite_commands() {
+    let mut pipeline = Pipeline::new();
+    pipeline.add_pass(paint_blue);
+    pipeline.add_pass(Optimize);
+    let mut tree = scene();
+    pipeline.run(&mut tree);
+    // All rectangles have the same color, so they are merged.
+    assert_eq!(tree.stats().draws(), 1);
+    assert_eq!(tree.stats().vertex_count, scene().stats().vertex_count);
+    assert!(tree.commands().all(|command| match command {
+        CommandRef::ChangeColor(color) => color == BLUE,
+        _ => true,
+    }));
+}
+
+#[cfg(feature = "software")]
+#[test]
+fn verification_reports_changing_pass() {
+    use graphics_tree::VerifyError;
+
+    let mut pipeline = Pipeline::default();
+    pipeline.add_pass(PromoteColors);
+    pipeline.add_pass(Optimize);
+    assert_eq!(pipeline.run_verified(&mut scene(), [32, 32]), Ok(()));
+
+    pipeline.add_pass(|tree: &mut GraphicsTree| tree.clear_color(RED));
+    let mut tree = scene();
+    let before = tree.to_bytes();
+    match pipeline.run_verified(&mut tree, [32, 32]) {
+        Err(VerifyError {pixels, ..}) => assert!(pixels > 0),
+        Ok(()) => panic!("Expected the last pass to fail verification"),
+    }
+    // The output of the passes is discarded.
+    assert_eq!(tree.to_bytes(), before);
+}
+
+#[cfg(feature = "software")]
+#[test]
+fn promoted_colors_are_verified_exactly() {
+    // Interpolating equal vertex colors must not round to other bytes.
+    let mut tree = GraphicsTree::new();
+    for i in 0..8 {
+        let x = -1.0 + 0.25 * i as f64;
+        let color = [0.5, 0.1 * i as f32, 0.5, 0.5 + 0.05 * i as f32];
+        graphics::rectangle(color, [x, -1.0, 0.2, 2.0], identity(), &mut tree);
+    }
+    let mut pipeline = Pipeline::new();
+    pipeline.add_pass(PromoteColors);
+    assert_eq!(pipeline.run_verified(&mut tree, [64, 64]), Ok(()));
+    assert_eq!(tree.stats().colors, 1);
+}
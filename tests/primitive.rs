@@ -0,0 +1,155 @@
+extern crate graphics;
+extern crate graphics_tree;
+extern crate image;
+extern crate texture;
+
+mod common;
+
+use graphics::math::{identity, scale};
+use graphics::Transformed;
+use graphics_tree::{CommandRef, GraphicsTree, PrimitiveKind, Texture, TextureBuffer};
+use image::RgbaImage;
+use common::{draw, draw_with_viewport, Call, Factory, Recorder};
+
+const RED: [f32; 4] = [1.0, 0.0, 0.0, 1.0];
+const BLUE: [f32; 4] = [0.0, 0.0, 1.0, 1.0];
+
+fn record(tree: &mut GraphicsTree, texture: &Texture) {
+    let m = identity().scale(0.01, 0.01);
+    graphics::rectangle(RED, [0.0, 0.0, 10.0, 20.0], m, tree);
+    graphics::ellipse(BLUE, [-50.0, -50.0, 30.0, 30.0], m, tree);
+    graphics::line(RED, 2.0, [0.0, 0.0, 40.0, -40.0], m, tree);
+    graphics::polygon(BLUE, &[[0.0, 0.0], [10.0, 0.0], [0.0, 10.0]], m, tree);
+    graphics::image(texture, m.trans(20.0, 20.0), tree);
+}
+
+#[test]
+fn primitives_are_recorded_when_semantic() {
+    let texture = Texture::from(RgbaImage::new(4, 4));
+    let mut tree = GraphicsTree::new();
+    tree.set_semantic(true);
+    record(&mut tree, &texture);
+    let kinds: Vec<&str> = tree.commands().map(|command| match command {
+        CommandRef::Primitive(primitive) => match primitive.kind {
+            PrimitiveKind::Rectangle(..) => "rectangle",
+            PrimitiveKind::Ellipse(..) => "ellipse",
+            PrimitiveKind::Line(..) => "line",
+            PrimitiveKind::Polygon(..) => "polygon",
+            PrimitiveKind::Image(..) => "image",
+        },
+        _ => "triangles",
+    }).collect();
+    assert_eq!(kinds, ["rectangle", "ellipse", "line", "polygon", "image"]);
+    assert_eq!(tree.stats().primitives, 5);
+    assert_eq!(tree.stats().vertex_count, 0);
+    assert_eq!(tree.stats().textures, 1);
+}
+
+#[test]
+fn primitives_draw_like_triangles() {
+    let texture = Texture::from(RgbaImage::new(4, 4));
+    let mut triangles = GraphicsTree::new();
+    record(&mut triangles, &texture);
+    let mut semantic = GraphicsTree::new();
+    semantic.set_semantic(true);
+    record(&mut semantic, &texture);
+    // Textures are created once per texture buffer.
+    let mut texture_buffer = TextureBuffer::new(Factory::default());
+    let mut expected = Recorder::default();
+    triangles.draw(&mut texture_buffer, &mut expected);
+    let mut recorder = Recorder::default();
+    semantic.draw(&mut texture_buffer, &mut recorder);
+    assert_eq!(recorder.calls, expected.calls);
+    assert_eq!(semantic.tessellate().to_dump(), triangles.to_dump());
+    assert_eq!(semantic.to_dump(), triangles.to_dump());
+}
+
+#[test]
+fn primitives_are_tessellated_with_child_transform() {
+    let m = scale(0.5, 0.25);
+    let mut tree = GraphicsTree::new();
+    tree.set_semantic(true);
+    {
+        let node = tree.add_child("child");
+        assert!(node.tree.is_semantic());
+        graphics::ellipse(RED, [-1.0, -1.0, 2.0, 2.0], identity(), &mut node.tree);
+        node.transform = m;
+    }
+    let flat = tree.flatten();
+    assert!(flat.children().is_empty());
+    match flat.commands().next() {
+        Some(CommandRef::Primitive(primitive)) => assert_eq!(primitive.transform, m),
+        command => panic!("Expected primitive, got {:?}", command),
+    }
+
+    let mut expected = GraphicsTree::new();
+    graphics::ellipse(RED, [-1.0, -1.0, 2.0, 2.0], m, &mut expected);
+    assert_eq!(draw(&tree).calls, draw(&expected).calls);
+    assert_eq!(tree.tessellate().to_dump(), expected.to_dump());
+}
+
+#[test]
+fn svg_exports_shapes() {
+    let mut tree = GraphicsTree::new();
+    tree.set_semantic(true);
+    let m = identity().scale(0.01, 0.01);
+    graphics::rectangle(RED, [0.0, 0.0, 10.0, 20.0], m, &mut tree);
+    graphics::ellipse(BLUE, [-50.0, -50.0, 30.0, 30.0], m, &mut tree);
+    graphics::line(RED, 2.0, [0.0, 0.0, 40.0, -40.0], m, &mut tree);
+    graphics::polygon(BLUE, &[[0.0, 0.0], [10.0, 0.0], [0.0, 10.0]], m, &mut tree);
+    let svg = tree.to_svg(200, 200);
+    assert!(svg.contains(
+        r#"<rect x="0" y="0" width="10" height="20" fill="rgb(255,0,0)" transform="matrix(1 0 0 -1 100 100)"/>"#
+    ));
+    assert!(svg.contains(r#"<ellipse cx="-35" cy="-35" rx="15" ry="15" fill="rgb(0,0,255)""#));
+    assert!(svg.contains(r#"<line x1="0" y1="0" x2="40" y2="-40" stroke-width="4" stroke-linecap="butt""#));
+    assert!(!svg.contains("<polygon"));
+    assert!(svg.contains("<path"));
+}
+
+#[test]
+fn consecutive_primitives_share_calls() {
+    let record = |tree: &mut GraphicsTree| {
+        graphics::rectangle(RED, [-1.0, -1.0, 0.5, 0.5], identity(), tree);
+        graphics::ellipse(RED, [0.0, 0.0, 0.5, 0.5], identity(), tree);
+        graphics::rectangle(BLUE, [0.5, -1.0, 0.5, 0.5], identity(), tree);
+    };
+    let mut triangles = GraphicsTree::new();
+    record(&mut triangles);
+    let mut semantic = GraphicsTree::new();
+    semantic.set_semantic(true);
+    record(&mut semantic);
+    let recorder = draw(&semantic);
+    assert_eq!(recorder.calls.len(), 2);
+    assert_eq!(recorder.vertices(), draw(&triangles).vertices());
+}
+
+#[test]
+fn primitives_are_culled_by_their_bounds() {
+    let mut tree = GraphicsTree::new();
+    tree.set_semantic(true);
+    // Only the thickness of the line reaches into the viewport.
+    graphics::line(RED, 0.2, [-0.5, -0.1, 0.5, -0.1], identity(), &mut tree);
+    graphics::rectangle(BLUE, [0.5, 0.5, 0.2, 0.2], identity(), &mut tree);
+    match draw_with_viewport(&tree, [-1.0, 0.0, 2.0, 0.4]).calls[..] {
+        [Call::Draw(_, color, _, _)] => assert_eq!(color, Some(RED)),
+        ref calls => panic!("unexpected calls {:?}", calls),
+    }
+}
+
+#[test]
+fn runs_of_primitives_are_updated_when_recording_more() {
+    let mut tree = GraphicsTree::new();
+    tree.set_semantic(true);
+    graphics::rectangle(RED, [-1.0, -1.0, 0.5, 0.5], identity(), &mut tree);
+    graphics::rectangle(RED, [0.0, 0.0, 0.5, 0.5], identity(), &mut tree);
+    assert_eq!(draw(&tree).calls.len(), 1);
+    graphics::rectangle(RED, [0.5, -1.0, 0.5, 0.5], identity(), &mut tree);
+    let recorder = draw(&tree);
+    assert_eq!(recorder.calls.len(), 1);
+    assert_eq!(recorder.vertices().len(), 18);
+    graphics::clear(BLUE, &mut tree);
+    graphics::rectangle(BLUE, [0.5, 0.5, 0.5, 0.5], identity(), &mut tree);
+    // Clearing clears color and stencil.
+    assert_eq!(draw(&tree).calls.len(), 4);
+}
@@ -0,0 +1,102 @@
+extern crate graphics;
+extern crate graphics_tree;
+extern crate image;
+extern crate texture;
+
+mod common;
+
+use graphics::{DrawState, Graphics};
+use graphics_tree::{CommandRef, GraphicsTree, Texture};
+use image::RgbaImage;
+use common::{draw, triangle, Call};
+
+const RED: [f32; 4] = [1.0, 0.0, 0.0, 1.0];
+const BLUE: [f32; 4] = [0.0, 0.0, 1.0, 1.0];
+
+#[test]
+fn colored_draws_are_promoted() {
+    let mut tree = GraphicsTree::new();
+    tree.tri_list(&DrawState::default(), &RED, |f| f(&triangle(0.0)));
+    tree.tri_list(&DrawState::default(), &BLUE, |f| f(&triangle(0.2)));
+    tree.tri_list(&DrawState::default(), &RED, |f| f(&triangle(0.4)));
+    tree.promote_colors();
+    let commands: Vec<_> = tree.commands().collect();
+    assert_eq!(commands.len(), 1);
+    match commands[0] {
+        CommandRef::Colors {vertices, colors} => {
+            assert_eq!(vertices, [triangle(0.0), triangle(0.2), triangle(0.4)].concat());
+            assert_eq!(colors, [[RED; 3], [BLUE; 3], [RED; 3]].concat());
+        }
+        ref command => panic!("Expected colors, got {:?}", command),
+    }
+    assert_eq!(draw(&tree).calls.len(), 1);
+}
+
+#[test]
+fn same_color_draws_are_not_promoted() {
+    let mut tree = GraphicsTree::new();
+    for i in 0..3 {
+        tree.tri_list(&DrawState::default(), &RED, |f| f(&triangle(i as f32 * 0.2)));
+    }
+    tree.promote_colors();
+    let commands: Vec<_> = tree.commands().collect();
+    assert_eq!(commands, [
+        CommandRef::ChangeColor(RED),
+        CommandRef::Colored {vertices: &[triangle(0.0), triangle(0.2), triangle(0.4)].concat()},
+    ]);
+}
+
+#[test]
+fn draw_state_changes_end_runs() {
+    let mut tree = GraphicsTree::new();
+    let clip = DrawState::new_clip();
+    tree.tri_list(&DrawState::default(), &RED, |f| f(&triangle(0.0)));
+    tree.tri_list(&clip, &BLUE, |f| f(&triangle(0.2)));
+    tree.tri_list(&clip, &RED, |f| f(&triangle(0.4)));
+    tree.tri_list(&DrawState::default(), &BLUE, |f| f(&triangle(0.6)));
+    tree.promote_colors();
+    assert_eq!(draw(&tree).calls, [
+        Call::Draw(DrawState::default(), Some(RED), None, vec![triangle(0.0).to_vec()]),
+        Call::Draw(clip, None, None, vec![[triangle(0.2), triangle(0.4)].concat()]),
+        Call::Draw(DrawState::default(), Some(BLUE), None, vec![triangle(0.6).to_vec()]),
+    ]);
+}
+
+#[test]
+fn tinted_draws_are_promoted_per_texture() {
+    let a = Texture::from(RgbaImage::new(4, 4));
+    let b = Texture::from(RgbaImage::new(4, 4));
+    let mut tree = GraphicsTree::new();
+    let ds = DrawState::default();
+    tree.tri_list_uv(&ds, &RED, &a, |f| f(&triangle(0.0), &triangle(0.0)));
+    tree.tri_list_uv(&ds, &BLUE, &a, |f| f(&triangle(0.2), &triangle(0.0)));
+    tree.tri_list_uv(&ds, &BLUE, &b, |f| f(&triangle(0.4), &triangle(0.0)));
+    tree.promote_colors();
+    let commands: Vec<_> = tree.commands().collect();
+    assert_eq!(commands.len(), 3);
+    match commands[0] {
+        CommandRef::TexturedColor {texture, colors, ..} => {
+            assert_eq!(*texture, a);
+            assert_eq!(colors, [[RED; 3], [BLUE; 3]].concat());
+        }
+        ref command => panic!("Expected textured color, got {:?}", command),
+    }
+    assert_eq!(commands[1], CommandRef::ChangeColor(BLUE));
+    assert_eq!(draw(&tree).calls.len(), 2);
+}
+
+#[cfg(feature = "software")]
+#[test]
+fn partial_triangles_end_runs() {
+    let mut tree = GraphicsTree::new();
+    // The fourth vertex is not drawn.
+    tree.tri_list(&DrawState::default(), &RED, |f| {
+        f(&[[-1.0, -1.0], [0.0, -1.0], [-1.0, 0.0], [1.0, 1.0]])
+    });
+    tree.tri_list(&DrawState::default(), &BLUE, |f| f(&[[1.0, -1.0], [0.0, 1.0], [0.5, 0.0]]));
+    tree.tri_list(&DrawState::default(), &RED, |f| f(&triangle(-0.5)));
+    let expected = tree.rasterize(8, 8);
+    tree.promote_colors();
+    assert_eq!(tree.rasterize(8, 8), expected);
+    assert_eq!(draw(&tree).calls.len(), 2);
+}
@@ -0,0 +1,87 @@
+extern crate graphics;
+extern crate graphics_tree;
+extern crate image;
+extern crate texture;
+
+mod common;
+
+use graphics::{DrawState, Graphics};
+use graphics_tree::{GraphicsTree, Texture, TextureBuffer};
+use image::RgbaImage;
+use common::{draw, triangle, Call, Factory, Recorder};
+
+const RED: [f32; 4] = [1.0, 0.0, 0.0, 1.0];
+const WHITE: [f32; 4] = [1.0; 4];
+
+#[test]
+fn separate_draws_are_grouped() {
+    let texture = Texture::from(RgbaImage::new(4, 4));
+    let mut tree = GraphicsTree::new();
+    for i in 0..3 {
+        let x = i as f32 * 0.4 - 1.0;
+        tree.tri_list_uv(&DrawState::default(), &WHITE, &texture, |f| f(&triangle(x), &triangle(0.0)));
+        tree.tri_list(&DrawState::default(), &RED, |f| f(&triangle(x + 0.2)));
+    }
+    let mut texture_buffer = TextureBuffer::new(Factory::default());
+    let mut recorder = Recorder::default();
+    tree.draw(&mut texture_buffer, &mut recorder);
+    let mut before = recorder.vertices();
+    tree.reorder();
+    let mut recorder = Recorder::default();
+    tree.draw(&mut texture_buffer, &mut recorder);
+    assert_eq!(recorder.calls.len(), 2);
+    let mut after = recorder.vertices();
+    before.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    after.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    assert_eq!(before, after);
+}
+
+#[test]
+fn overlapping_draws_keep_order() {
+    let mut tree = GraphicsTree::new();
+    tree.tri_list(&DrawState::default(), &RED, |f| f(&triangle(0.0)));
+    tree.tri_list(&DrawState::default(), &WHITE, |f| f(&triangle(0.05)));
+    tree.tri_list(&DrawState::default(), &RED, |f| f(&triangle(0.1)));
+    tree.reorder();
+    let calls = draw(&tree).calls;
+    assert_eq!(calls, [
+        Call::Draw(DrawState::default(), Some(RED), None, vec![triangle(0.0).to_vec()]),
+        Call::Draw(DrawState::default(), Some(WHITE), None, vec![triangle(0.05).to_vec()]),
+        Call::Draw(DrawState::default(), Some(RED), None, vec![triangle(0.1).to_vec()]),
+    ]);
+}
+
+#[test]
+fn draws_are_not_moved_across_clears() {
+    let mut tree = GraphicsTree::new();
+    tree.tri_list(&DrawState::default(), &RED, |f| f(&triangle(-0.5)));
+    tree.clear_color(WHITE);
+    tree.tri_list(&DrawState::default(), &RED, |f| f(&triangle(0.5)));
+    tree.reorder();
+    assert_eq!(draw(&tree).calls, [
+        Call::Draw(DrawState::default(), Some(RED), None, vec![triangle(-0.5).to_vec()]),
+        Call::ClearColor(WHITE),
+        Call::Draw(DrawState::default(), Some(RED), None, vec![triangle(0.5).to_vec()]),
+    ]);
+}
+
+#[test]
+fn many_draws_are_grouped() {
+    let blue = [0.0, 0.0, 1.0, 1.0];
+    let small = |x: f32, y: f32| [[x, y], [x + 0.05, y], [x, y + 0.05]];
+    let mut tree = GraphicsTree::new();
+    for i in 0..400 {
+        let (x, y) = ((i % 20) as f32 * 0.1 - 1.0, (i / 20) as f32 * 0.1 - 1.0);
+        let color = if i % 2 == 0 {RED} else {WHITE};
+        tree.tri_list(&DrawState::default(), &color, |f| f(&small(x, y)));
+    }
+    // Covers draws of both colors, so it stays on top.
+    tree.tri_list(&DrawState::default(), &blue, |f| f(&[[-0.5, -0.5], [0.5, -0.5], [-0.5, 0.5]]));
+    tree.reorder();
+    let calls = draw(&tree).calls;
+    let colors: Vec<_> = calls.iter().map(|call| match *call {
+        Call::Draw(_, color, _, _) => color.unwrap(),
+        _ => panic!("unexpected call {:?}", call),
+    }).collect();
+    assert_eq!(colors, [RED, WHITE, blue]);
+}
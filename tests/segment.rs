@@ -0,0 +1,81 @@
+extern crate graphics;
+extern crate graphics_tree;
+extern crate texture;
+
+mod common;
+
+use graphics::{DrawState, Graphics};
+use graphics_tree::GraphicsTree;
+use common::{draw, Call};
+
+const RED: [f32; 4] = [1.0, 0.0, 0.0, 1.0];
+const BLUE: [f32; 4] = [0.0, 0.0, 1.0, 1.0];
+const TRIANGLE: [[f32; 2]; 3] = [[0.0, 0.0], [0.5, 0.0], [0.0, 0.5]];
+const MOVED: [[f32; 2]; 3] = [[-0.5, -0.5], [0.0, -0.5], [-0.5, 0.0]];
+
+#[test]
+fn rerecord_replaces_only_the_segment() {
+    let mut tree = GraphicsTree::new();
+    tree.tri_list(&DrawState::default(), &RED, |f| f(&TRIANGLE));
+    let segment = tree.record_segment(|tree| {
+        tree.tri_list(&DrawState::default(), &BLUE, |f| f(&TRIANGLE));
+    });
+    tree.tri_list(&DrawState::default(), &RED, |f| f(&TRIANGLE));
+
+    tree.rerecord_segment(segment, |tree| {
+        tree.tri_list(&DrawState::default(), &BLUE, |f| f(&MOVED));
+    });
+    assert_eq!(draw(&tree).calls, [
+        Call::Draw(DrawState::default(), Some(RED), None, vec![TRIANGLE.to_vec()]),
+        Call::Draw(DrawState::default(), Some(BLUE), None, vec![MOVED.to_vec()]),
+        Call::Draw(DrawState::default(), Some(RED), None, vec![TRIANGLE.to_vec()]),
+    ]);
+    assert!(tree.child("").is_none());
+}
+
+#[test]
+fn dirty_region_covers_old_and_new_geometry() {
+    let mut tree = GraphicsTree::new();
+    let segment = tree.record_segment(|tree| {
+        tree.tri_list(&DrawState::default(), &BLUE, |f| f(&TRIANGLE));
+    });
+    assert_eq!(tree.dirty_region(), Some([0.0, 0.0, 0.5, 0.5]));
+    tree.clear_dirty_region();
+    assert_eq!(tree.dirty_region(), None);
+
+    tree.rerecord_segment(segment, |tree| {
+        tree.tri_list(&DrawState::default(), &BLUE, |f| f(&MOVED));
+    });
+    assert_eq!(tree.dirty_region(), Some([-0.5, -0.5, 1.0, 1.0]));
+    assert_eq!(tree.dirty_scissor([100, 100]), Some([25, 25, 50, 50]));
+}
+
+#[test]
+fn clear_resets_dirty_region() {
+    let mut tree = GraphicsTree::new();
+    tree.record_segment(|tree| {
+        tree.tri_list(&DrawState::default(), &BLUE, |f| f(&TRIANGLE));
+    });
+    tree.clear();
+    assert_eq!(tree.dirty_region(), None);
+    assert_eq!(tree.dirty_scissor([100, 100]), None);
+}
+
+#[test]
+fn invalidate_marks_segment_dirty() {
+    let mut tree = GraphicsTree::new();
+    let segment = tree.record_segment(|tree| {
+        tree.tri_list(&DrawState::default(), &BLUE, |f| f(&TRIANGLE));
+    });
+    tree.clear_dirty_region();
+    assert!(!tree.is_dirty(segment));
+
+    tree.invalidate(segment);
+    assert!(tree.is_dirty(segment));
+    assert_eq!(tree.dirty_region(), Some([0.0, 0.0, 0.5, 0.5]));
+    assert_eq!(tree.dirty_scissor([10, 20]), Some([5, 5, 3, 5]));
+
+    tree.rerecord_segment(segment, |_| {});
+    assert!(!tree.is_dirty(segment));
+    assert_eq!(tree.dirty_region(), Some([0.0, 0.0, 0.5, 0.5]));
+}
@@ -0,0 +1,93 @@
+#![cfg(feature = "software")]
+
+extern crate graphics;
+extern crate graphics_tree;
+extern crate image;
+
+use graphics::{DrawState, Graphics};
+use graphics::math::identity;
+use graphics_tree::{GraphicsTree, Rasterizer, Texture};
+use image::Rgba;
+
+const RED: [f32; 4] = [1.0, 0.0, 0.0, 1.0];
+
+/// Full screen quad in normalized device coordinates.
+const QUAD: [[f32; 2]; 6] = [
+    [-1.0, -1.0], [1.0, -1.0], [-1.0, 1.0],
+    [1.0, -1.0], [1.0, 1.0], [-1.0, 1.0],
+];
+
+#[test]
+fn clear_and_rectangle() {
+    let mut tree = GraphicsTree::new();
+    tree.clear_color([1.0; 4]);
+    // Left half of an 8x8 image, using pixel coordinates.
+    let transform = graphics::math::multiply(
+        [[2.0 / 8.0, 0.0, -1.0], [0.0, -2.0 / 8.0, 1.0]],
+        identity()
+    );
+    graphics::rectangle(RED, [0.0, 0.0, 4.0, 8.0], transform, &mut tree);
+    let image = tree.rasterize(8, 8);
+    assert_eq!(image.get_pixel(0, 0), &Rgba([255, 0, 0, 255]));
+    assert_eq!(image.get_pixel(3, 7), &Rgba([255, 0, 0, 255]));
+    assert_eq!(image.get_pixel(4, 0), &Rgba([255, 255, 255, 255]));
+    assert_eq!(image.get_pixel(7, 7), &Rgba([255, 255, 255, 255]));
+}
+
+#[test]
+fn shared_edges_are_drawn_once() {
+    let mut tree = GraphicsTree::new();
+    tree.tri_list(&DrawState::default().blend(graphics::draw_state::Blend::Add),
+        &[0.25, 0.0, 0.0, 0.25], |f| f(&QUAD));
+    let image = tree.rasterize(16, 16);
+    assert!(image.pixels().all(|p| p == &Rgba([64, 0, 0, 64])));
+}
+
+#[test]
+fn per_vertex_colors() {
+    let mut tree = GraphicsTree::new();
+    let colors = [RED, RED, RED, [0.0, 0.0, 1.0, 1.0], [0.0, 0.0, 1.0, 1.0], RED];
+    tree.tri_list_c(&DrawState::default(), |f| f(&QUAD, &colors));
+    let image = tree.rasterize(4, 4);
+    assert_eq!(image.get_pixel(0, 3), &Rgba([255, 0, 0, 255]));
+    assert!(image.get_pixel(3, 0)[2] > 128);
+}
+
+#[test]
+fn textured_samples_image() {
+    let mut source = image::RgbaImage::new(2, 2);
+    source.put_pixel(0, 0, Rgba([255, 0, 0, 255]));
+    source.put_pixel(1, 0, Rgba([0, 255, 0, 255]));
+    source.put_pixel(0, 1, Rgba([0, 0, 255, 255]));
+    source.put_pixel(1, 1, Rgba([255, 255, 255, 255]));
+    let texture: Texture = source.into();
+    let uvs = [
+        [0.0, 1.0], [1.0, 1.0], [0.0, 0.0],
+        [1.0, 1.0], [1.0, 0.0], [0.0, 0.0],
+    ];
+
+    let mut tree = GraphicsTree::new();
+    tree.tri_list_uv(&DrawState::default(), &[1.0; 4], &texture, |f| f(&QUAD, &uvs));
+    tree.tri_list_uv_c(&DrawState::default(), &texture, |f| f(&[], &[], &[]));
+    let image = tree.rasterize(4, 4);
+    assert_eq!(image.get_pixel(0, 0), &Rgba([255, 0, 0, 255]));
+    assert_eq!(image.get_pixel(3, 0), &Rgba([0, 255, 0, 255]));
+    assert_eq!(image.get_pixel(0, 3), &Rgba([0, 0, 255, 255]));
+    assert_eq!(image.get_pixel(3, 3), &Rgba([255, 255, 255, 255]));
+}
+
+#[test]
+fn scissor_and_stencil() {
+    let mut tree = GraphicsTree::new();
+    tree.clear_stencil(0);
+    // Write the stencil in the top half only.
+    tree.tri_list(&DrawState::new_clip().scissor([0, 0, 4, 2]), &RED, |f| f(&QUAD));
+    tree.tri_list(&DrawState::new_inside(), &RED, |f| f(&QUAD));
+    let mut rasterizer = Rasterizer::new(4, 4);
+    rasterizer.draw(&tree);
+    assert_eq!(&rasterizer.stencil()[..8], &[255; 8]);
+    assert_eq!(&rasterizer.stencil()[8..], &[0; 8]);
+    let image = rasterizer.image();
+    assert_eq!(image.get_pixel(0, 0), &Rgba([255, 0, 0, 255]));
+    assert_eq!(image.get_pixel(0, 3), &Rgba([0, 0, 0, 0]));
+}
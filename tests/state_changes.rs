@@ -0,0 +1,98 @@
+extern crate graphics;
+extern crate graphics_tree;
+
+use graphics::{DrawState, Graphics};
+use graphics::math::identity;
+use graphics_tree::{CommandRef, GraphicsTree};
+
+const RED: [f32; 4] = [1.0, 0.0, 0.0, 1.0];
+const BLUE: [f32; 4] = [0.0, 0.0, 1.0, 1.0];
+
+/// Returns the kinds of the recorded commands.
+fn kinds(tree: &GraphicsTree) -> Vec<&'static str> {
+    tree.commands().map(|command| match command {
+        CommandRef::ClearColor(_) => "ClearColor",
+        CommandRef::ClearStencil(_) => "ClearStencil",
+        CommandRef::ChangeColor(_) => "ChangeColor",
+        CommandRef::ChangeDrawState(_) => "ChangeDrawState",
+        CommandRef::Colored {..} => "Colored",
+        CommandRef::Colors {..} => "Colors",
+        CommandRef::Textured {..} => "Textured",
+        CommandRef::TexturedColor {..} => "TexturedColor",
+        _ => "Other",
+    }).collect()
+}
+
+#[test]
+fn same_color_is_recorded_once() {
+    let mut tree = GraphicsTree::new();
+    graphics::rectangle(RED, [0.0, 0.0, 1.0, 1.0], identity(), &mut tree);
+    graphics::rectangle(RED, [2.0, 0.0, 1.0, 1.0], identity(), &mut tree);
+    assert_eq!(kinds(&tree), ["ChangeColor", "Colored", "Colored"]);
+}
+
+#[test]
+fn clear_resets_state() {
+    let mut tree = GraphicsTree::new();
+    graphics::rectangle(RED, [0.0, 0.0, 1.0, 1.0], identity(), &mut tree);
+    tree.clear();
+    graphics::rectangle(RED, [0.0, 0.0, 1.0, 1.0], identity(), &mut tree);
+    assert_eq!(kinds(&tree), ["ChangeColor", "Colored"]);
+}
+
+#[test]
+fn draw_state_changes_are_tracked() {
+    let mut tree = GraphicsTree::new();
+    let clip = DrawState::new_clip();
+    tree.tri_list(&clip, &RED, |f| f(&[[0.0; 2]; 3]));
+    tree.tri_list(&clip, &RED, |f| f(&[[0.0; 2]; 3]));
+    tree.tri_list(&DrawState::default(), &RED, |f| f(&[[0.0; 2]; 3]));
+    assert_eq!(kinds(&tree), [
+        "ChangeColor", "ChangeDrawState", "Colored", "Colored",
+        "ChangeDrawState", "Colored"
+    ]);
+}
+
+#[test]
+fn optimize_removes_overwritten_state_changes() {
+    let mut tree = GraphicsTree::new();
+    // Draws without vertices do not consume the state.
+    tree.tri_list(&DrawState::new_clip(), &RED, |_| {});
+    tree.tri_list(&DrawState::default(), &BLUE, |f| f(&[[0.0; 2]; 3]));
+    assert_eq!(kinds(&tree), [
+        "ChangeColor", "ChangeDrawState", "Colored",
+        "ChangeColor", "ChangeDrawState", "Colored"
+    ]);
+    tree.optimize();
+    let commands: Vec<_> = tree.commands().collect();
+    assert_eq!(commands, [
+        CommandRef::ChangeColor(BLUE),
+        CommandRef::Colored {vertices: &[[0.0; 2]; 3]},
+    ]);
+}
+
+#[test]
+fn optimize_keeps_recording_state_consistent() {
+    let mut tree = GraphicsTree::new();
+    tree.tri_list(&DrawState::default(), &RED, |f| f(&[[0.0; 2]; 3]));
+    tree.tri_list(&DrawState::default(), &BLUE, |_| {});
+    tree.optimize();
+    // The blue color change was removed, so it must be recorded again.
+    tree.tri_list(&DrawState::default(), &BLUE, |f| f(&[[0.0; 2]; 3]));
+    assert_eq!(kinds(&tree), ["ChangeColor", "Colored", "ChangeColor", "Colored"]);
+}
+
+#[test]
+fn optimize_merges_same_color() {
+    let mut tree = GraphicsTree::new();
+    for i in 0..10 {
+        graphics::rectangle(RED, [i as f64, 0.0, 1.0, 1.0], identity(), &mut tree);
+    }
+    graphics::rectangle(BLUE, [0.0, 0.0, 1.0, 1.0], identity(), &mut tree);
+    tree.optimize();
+    assert_eq!(kinds(&tree), ["ChangeColor", "Colored", "ChangeColor", "Colored"]);
+    match tree.commands().nth(1) {
+        Some(CommandRef::Colored {vertices}) => assert_eq!(vertices.len(), 60),
+        _ => panic!("Expected colored draw"),
+    }
+}
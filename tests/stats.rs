@@ -0,0 +1,99 @@
+extern crate graphics;
+extern crate graphics_tree;
+extern crate image;
+extern crate texture;
+
+mod common;
+
+use graphics::math::identity;
+use graphics::{DrawState, Graphics, BACK_END_MAX_VERTEX_COUNT};
+use graphics_tree::{GraphicsTree, Stats, Texture, TextureBuffer};
+use image::RgbaImage;
+use common::{draw, Factory, Recorder};
+
+const RED: [f32; 4] = [1.0, 0.0, 0.0, 1.0];
+const BLUE: [f32; 4] = [0.0, 0.0, 1.0, 1.0];
+const TRIANGLE: [[f32; 2]; 3] = [[0.0, 0.0], [0.5, 0.0], [0.0, 0.5]];
+
+fn record() -> GraphicsTree {
+    let texture = Texture::from(RgbaImage::new(2, 2));
+    let mut tree = GraphicsTree::new();
+    tree.clear_color(BLUE);
+    tree.tri_list(&DrawState::default(), &RED, |f| f(&TRIANGLE));
+    tree.tri_list_uv(&DrawState::default(), &BLUE, &texture, |f| f(&TRIANGLE, &TRIANGLE));
+    {
+        let node = tree.add_child("child");
+        node.tree.tri_list_uv(&DrawState::new_alpha(), &RED, &texture, |f| f(&TRIANGLE, &TRIANGLE));
+        let large = vec![[0.0; 2]; BACK_END_MAX_VERTEX_COUNT + 3];
+        node.tree.tri_list(&DrawState::new_alpha(), &RED, |f| f(&large));
+    }
+    tree
+}
+
+#[test]
+fn stats_count_commands_and_data() {
+    let tree = record();
+    assert_eq!(tree.stats(), Stats {
+        clear_color: 1,
+        change_color: 3,
+        colored: 2,
+        textured: 2,
+        children: 1,
+        vertex_count: 12 + BACK_END_MAX_VERTEX_COUNT,
+        uv_count: 6,
+        textures: 1,
+        backend_calls: 5,
+        chunks: 5,
+        ..Stats::default()
+    });
+    assert_eq!(tree.stats().state_changes(), 3);
+    assert_eq!(tree.stats().draws(), 4);
+}
+
+#[test]
+fn hidden_children_are_not_counted() {
+    let mut tree = record();
+    tree.child_mut("child").unwrap().visible = false;
+    let stats = tree.stats();
+    assert_eq!(stats.children, 0);
+    assert_eq!(stats.draws(), 2);
+}
+
+#[test]
+fn profile_counts_drawn_commands() {
+    let tree = record();
+    let profile = tree.draw_profiled(&mut TextureBuffer::new(Factory::default()), &mut Recorder::default());
+    assert_eq!(profile.clear.count, 1);
+    assert_eq!(profile.colored.count, 2);
+    assert_eq!(profile.textured.count, 2);
+    assert_eq!(profile.colors.count, 0);
+    assert!(profile.total >= profile.textured.time);
+}
+
+#[test]
+fn backend_calls_count_merged_primitives() {
+    let mut tree = GraphicsTree::new();
+    tree.set_semantic(true);
+    graphics::rectangle(RED, [-1.0, -1.0, 0.5, 0.5], identity(), &mut tree);
+    graphics::rectangle(RED, [0.0, 0.0, 0.5, 0.5], identity(), &mut tree);
+    graphics::rectangle(BLUE, [0.5, -1.0, 0.5, 0.5], identity(), &mut tree);
+    let stats = tree.stats();
+    assert_eq!(stats.primitives, 3);
+    assert_eq!(stats.backend_calls, 2);
+    assert_eq!(stats.backend_calls, draw(&tree).calls.len());
+}
+
+#[test]
+fn profile_counts_draws_batched_in_atlas() {
+    let textures: Vec<Texture> = (0..2).map(|_| Texture::from(RgbaImage::new(4, 4))).collect();
+    let mut tree = GraphicsTree::new();
+    for texture in &textures {
+        tree.tri_list_uv(&DrawState::default(), &RED, texture, |f| f(&TRIANGLE, &TRIANGLE));
+    }
+    let mut texture_buffer = TextureBuffer::new(Factory::default());
+    texture_buffer.enable_atlas(32, 16);
+    let mut recorder = Recorder::default();
+    let profile = tree.draw_profiled(&mut texture_buffer, &mut recorder);
+    assert_eq!(recorder.calls.len(), 1);
+    assert_eq!(profile.textured.count, 2);
+}
@@ -0,0 +1,79 @@
+extern crate graphics;
+extern crate graphics_tree;
+extern crate image;
+
+use graphics::{DrawState, Graphics};
+use graphics_tree::{GraphicsTree, Texture};
+
+const TRIANGLE: [[f32; 2]; 3] = [[-1.0, -1.0], [1.0, -1.0], [-1.0, 1.0]];
+
+#[test]
+fn colored_triangles_become_paths() {
+    let mut tree = GraphicsTree::new();
+    tree.clear_color([1.0; 4]);
+    tree.tri_list(&DrawState::default(), &[1.0, 0.0, 0.0, 0.5], |f| f(&TRIANGLE));
+    let svg = tree.to_svg(10, 20);
+    assert!(svg.starts_with("<svg "));
+    assert!(svg.contains(r#"viewBox="0 0 10 20""#));
+    assert!(svg.contains(r#"<rect width="10" height="20" fill="rgb(255,255,255)"/>"#));
+    assert!(svg.contains(r#"<path d="M0 20L0 0L10 20Z" fill="rgb(255,0,0)" fill-opacity="0.5"/>"#));
+    assert!(svg.trim_end().ends_with("</svg>"));
+}
+
+#[test]
+fn vertex_colors_are_subdivided() {
+    let mut tree = GraphicsTree::new();
+    tree.tri_list_c(&DrawState::default(), |f| {
+        f(&TRIANGLE, &[[1.0, 0.0, 0.0, 1.0], [0.0, 1.0, 0.0, 1.0], [0.0, 0.0, 1.0, 1.0]])
+    });
+    let svg = tree.to_svg(10, 10);
+    assert_eq!(svg.matches("<path ").count(), 16);
+}
+
+#[test]
+fn textures_are_embedded_once() {
+    let texture: Texture = image::RgbaImage::new(4, 4).into();
+    let uvs = [[0.0, 1.0], [1.0, 1.0], [0.0, 0.0]];
+    let mut tree = GraphicsTree::new();
+    tree.tri_list_uv(&DrawState::default(), &[1.0; 4], &texture, |f| f(&TRIANGLE, &uvs));
+    tree.tri_list_uv(&DrawState::default(), &[0.5; 4], &texture, |f| f(&TRIANGLE, &uvs));
+    let svg = tree.to_svg(8, 8);
+    assert_eq!(svg.matches("<image ").count(), 1);
+    assert!(svg.contains("data:image/png;base64,iVBORw0KGgo"));
+    assert_eq!(svg.matches("<use ").count(), 2);
+    assert_eq!(svg.matches("<feColorMatrix ").count(), 1);
+    // Maps image pixels to the view box: x' = 2x, y' = 2y.
+    assert!(svg.contains(r#"transform="matrix(2 0 0 2 0 0)""#));
+}
+
+#[test]
+fn empty_textures_are_skipped() {
+    let texture: Texture = image::RgbaImage::new(0, 0).into();
+    let uvs = [[0.0, 1.0], [1.0, 1.0], [0.0, 0.0]];
+    let mut tree = GraphicsTree::new();
+    tree.tri_list_uv(&DrawState::default(), &[1.0; 4], &texture, |f| f(&TRIANGLE, &uvs));
+    let svg = tree.to_svg(8, 8);
+    assert_eq!(svg.matches("<image ").count(), 0);
+    assert_eq!(svg.matches("<use ").count(), 0);
+}
+
+#[test]
+fn scissor_and_stencil() {
+    let mut tree = GraphicsTree::new();
+    tree.tri_list(&DrawState::new_clip(), &[1.0; 4], |f| f(&TRIANGLE));
+    tree.tri_list(&DrawState::default().scissor([1, 2, 3, 4]), &[1.0; 4], |f| f(&TRIANGLE));
+    let svg = tree.to_svg(8, 8);
+    assert_eq!(svg.matches("<path ").count(), 1);
+    assert!(svg.contains(r#"<rect x="1" y="2" width="3" height="4"/>"#));
+}
+
+#[test]
+fn clears_replace_earlier_draws() {
+    let mut tree = GraphicsTree::new();
+    tree.tri_list(&DrawState::default(), &[1.0, 0.0, 0.0, 1.0], |f| f(&TRIANGLE));
+    tree.clear_color([0.0, 0.0, 1.0, 0.5]);
+    let svg = tree.to_svg(10, 10);
+    assert!(!svg.contains("<path "));
+    assert_eq!(svg.matches("<rect ").count(), 1);
+    assert!(svg.contains(r#"<rect width="10" height="10" fill="rgb(0,0,255)" fill-opacity="0.5"/>"#));
+}
@@ -0,0 +1,183 @@
+extern crate graphics;
+extern crate graphics_tree;
+extern crate texture;
+
+mod common;
+
+use graphics::math::identity;
+use graphics::{DrawState, Graphics};
+use graphics_tree::{CommandRef, GraphicsTree, Hit, TextureBuffer};
+use common::{draw, triangle, Call, Factory, Recorder};
+
+const RED: [f32; 4] = [1.0, 0.0, 0.0, 1.0];
+const BLUE: [f32; 4] = [0.0, 0.0, 1.0, 1.0];
+
+/// Records an untagged draw, a draw tagged 1 with a nested draw tagged 2,
+/// and a draw tagged 2.
+fn record() -> GraphicsTree {
+    let mut tree = GraphicsTree::new();
+    tree.tri_list(&DrawState::default(), &RED, |f| f(&triangle(-1.0)));
+    tree.record_tagged(1, |tree| {
+        tree.tri_list(&DrawState::default(), &BLUE, |f| f(&triangle(-0.5)));
+        tree.record_tagged(2, |tree| {
+            tree.tri_list(&DrawState::default(), &BLUE, |f| f(&triangle(0.0)));
+        });
+    });
+    tree.push_tag(2);
+    tree.tri_list(&DrawState::default(), &BLUE, |f| f(&triangle(0.5)));
+    tree.pop_tag();
+    tree
+}
+
+fn draw_tagged(tree: &GraphicsTree, tag: u64) -> Recorder {
+    let mut recorder = Recorder::default();
+    tree.draw_tagged(tag, &mut TextureBuffer::new(Factory::default()), &mut recorder);
+    recorder
+}
+
+#[test]
+fn tags_are_inspected() {
+    let tree = record();
+    let commands: Vec<_> = tree.commands().collect();
+    assert_eq!(commands[2], CommandRef::PushTag(1));
+    assert_eq!(commands[5], CommandRef::PushTag(2));
+    assert_eq!(commands[7], CommandRef::PopTag);
+    assert_eq!(tree.command_tags(), [
+        None, None,
+        None, Some(1), Some(1),
+        Some(1), Some(2), Some(1),
+        None,
+        None, Some(2), None,
+    ]);
+}
+
+#[test]
+fn draw_tagged_draws_nested_tags() {
+    let tree = record();
+    assert_eq!(draw_tagged(&tree, 1).vertices(), [triangle(-0.5), triangle(0.0)].concat());
+    assert_eq!(draw_tagged(&tree, 2).vertices(), [triangle(0.0), triangle(0.5)].concat());
+    assert_eq!(draw_tagged(&tree, 3).calls, []);
+
+    // Colors are set by untagged state changes.
+    match draw_tagged(&tree, 1).calls[0] {
+        Call::Draw(_, color, _, _) => assert_eq!(color, Some(BLUE)),
+        ref call => panic!("unexpected call {:?}", call),
+    }
+}
+
+#[test]
+fn draw_tagged_draws_children() {
+    let mut tree = GraphicsTree::new();
+    tree.push_tag(1);
+    {
+        let node = tree.add_child("child");
+        node.tree.tri_list(&DrawState::default(), &RED, |f| f(&triangle(0.0)));
+        node.tree.record_tagged(2, |tree| {
+            tree.tri_list(&DrawState::default(), &BLUE, |f| f(&triangle(0.5)));
+        });
+    }
+    tree.pop_tag();
+    assert_eq!(draw_tagged(&tree, 1).vertices(), [triangle(0.0), triangle(0.5)].concat());
+    assert_eq!(draw_tagged(&tree, 2).vertices(), triangle(0.5));
+}
+
+#[test]
+fn remove_tag_keeps_other_commands() {
+    let mut tree = record();
+    tree.remove_tag(1);
+    let recorder = draw(&tree);
+    assert_eq!(recorder.vertices(), [triangle(-1.0), triangle(0.5)].concat());
+    // The color change used by the last draw is kept.
+    match recorder.calls[1] {
+        Call::Draw(_, color, _, _) => assert_eq!(color, Some(BLUE)),
+        ref call => panic!("unexpected call {:?}", call),
+    }
+    // The color change is moved to the draw tagged 2.
+    assert_eq!(tree.command_tags().iter().filter(|tag| tag.is_some()).count(), 2);
+
+    tree.remove_tag(2);
+    assert_eq!(tree.commands().count(), 2);
+}
+
+#[test]
+fn hits_report_tags() {
+    let mut tree = GraphicsTree::new();
+    graphics::rectangle(RED, [-1.0, -1.0, 1.0, 1.0], identity(), &mut tree);
+    tree.record_tagged(1, |tree| {
+        graphics::rectangle(BLUE, [0.0, 0.0, 1.0, 1.0], identity(), tree);
+        let node = tree.add_child("child");
+        graphics::rectangle(RED, [0.0, -1.0, 1.0, 1.0], identity(), &mut node.tree);
+    });
+    assert_eq!(tree.hit_test([-0.5, -0.5]).unwrap().tag, None);
+    assert_eq!(tree.hit_test([0.5, 0.5]).unwrap().tag, Some(1));
+    assert_eq!(tree.hit_test([0.5, -0.5]), Some(Hit {path: vec![0], command: 1, tag: Some(1)}));
+}
+
+#[test]
+fn tags_are_dumped() {
+    let tree = record();
+    let loaded = GraphicsTree::from_bytes(&tree.to_bytes()).unwrap();
+    assert_eq!(loaded.command_tags(), tree.command_tags());
+    assert_eq!(draw_tagged(&loaded, 2).vertices(), draw_tagged(&tree, 2).vertices());
+}
+
+#[test]
+fn removed_children_are_added_again() {
+    let mut tree = GraphicsTree::new();
+    tree.record_tagged(1, |tree| {
+        let node = tree.add_child("child");
+        node.tree.tri_list(&DrawState::default(), &RED, |f| f(&triangle(0.0)));
+    });
+    tree.remove_tag(1);
+    assert!(tree.child("child").is_none());
+    {
+        let node = tree.add_child("child");
+        assert!(node.tree.is_empty());
+        node.tree.tri_list(&DrawState::default(), &BLUE, |f| f(&triangle(0.5)));
+    }
+    assert!(tree.child_mut("child").is_some());
+    assert_eq!(draw(&tree).vertices(), triangle(0.5));
+}
+
+#[cfg(feature = "software")]
+#[test]
+fn occluders_only_remove_draws_with_the_same_tags() {
+    let mut tree = GraphicsTree::new();
+    tree.record_tagged(1, |tree| graphics::rectangle(RED, [-1.0, -1.0, 2.0, 2.0], identity(), tree));
+    tree.record_tagged(2, |tree| graphics::rectangle(BLUE, [-1.0, -1.0, 2.0, 2.0], identity(), tree));
+    let mut expected = tree.clone();
+    expected.remove_tag(2);
+    tree.remove_occluded();
+    tree.remove_tag(2);
+    assert_eq!(tree.rasterize(4, 4), expected.rasterize(4, 4));
+    assert_eq!(tree.rasterize(4, 4).get_pixel(0, 0).0, [255, 0, 0, 255]);
+}
+
+#[test]
+fn removed_data_is_dropped() {
+    let mut tree = record();
+    tree.set_semantic(true);
+    let record_widget = |tree: &mut GraphicsTree| tree.record_tagged(3, |tree| {
+        tree.tri_list_c(&DrawState::default(), |f| f(&triangle(0.2), &[RED; 3]));
+        graphics::rectangle(BLUE, [0.0, 0.0, 0.5, 0.5], identity(), tree);
+        let node = tree.add_child("widget");
+        node.tree.tri_list(&DrawState::default(), &RED, |f| f(&triangle(0.3)));
+    });
+    record_widget(&mut tree);
+    draw(&tree);
+    let (stats, memory_usage) = (tree.stats(), tree.memory_usage());
+    for _ in 0..10 {
+        tree.remove_tag(3);
+        assert_eq!(tree.children().len(), 0);
+        record_widget(&mut tree);
+        draw(&tree);
+    }
+    assert_eq!(tree.stats(), stats);
+    assert_eq!(tree.memory_usage(), memory_usage);
+
+    tree.remove_tag(1);
+    let expected = [triangle(-1.0), triangle(0.5)].concat();
+    assert_eq!(draw(&tree).vertices()[..6], expected[..]);
+    // The vertices of the widget child are included.
+    assert_eq!(tree.stats().vertex_count, 6 + 3 + 3);
+}
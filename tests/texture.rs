@@ -0,0 +1,384 @@
+extern crate graphics;
+extern crate graphics_tree;
+extern crate image;
+extern crate texture;
+
+mod common;
+
+use std::panic;
+
+use graphics::{DrawState, Graphics, ImageSize};
+use graphics_tree::{DrawError, GraphicsTree, Texture, TextureBuffer};
+use image::{Rgba, RgbaImage};
+use texture::{Filter, TextureSettings};
+use common::{Call, Factory, MockTexture, Recorder};
+
+const WHITE: [f32; 4] = [1.0; 4];
+const TRIANGLE: [[f32; 2]; 3] = [[0.0, 0.0], [0.5, 0.0], [0.0, 0.5]];
+
+fn record(texture: &Texture) -> GraphicsTree {
+    let mut tree = GraphicsTree::new();
+    tree.tri_list_uv(&DrawState::default(), &WHITE, texture, |f| f(&TRIANGLE, &TRIANGLE));
+    tree
+}
+
+fn draw(tree: &GraphicsTree, texture_buffer: &mut TextureBuffer<Factory, MockTexture>) {
+    tree.draw(texture_buffer, &mut Recorder::default());
+}
+
+#[test]
+fn changed_region_is_updated() {
+    let texture = Texture::from(RgbaImage::new(8, 8));
+    let tree = record(&texture);
+    let mut texture_buffer = TextureBuffer::with_update(Factory::default());
+    draw(&tree, &mut texture_buffer);
+    assert_eq!(texture_buffer.factory.created, 1);
+
+    texture.with_region_mut([2, 3, 1, 1], |image| image.put_pixel(2, 3, Rgba([255; 4])));
+    texture.with_region_mut([4, 1, 1, 1], |image| image.put_pixel(4, 1, Rgba([255; 4])));
+    assert_eq!(texture.0.read().unwrap().dirty_rect, Some([2, 1, 3, 3]));
+    draw(&tree, &mut texture_buffer);
+    assert_eq!(texture_buffer.factory.created, 1);
+    assert_eq!(texture_buffer.factory.updated, [([2, 1], [3, 3], 3 * 3 * 4)]);
+
+    // Nothing changed, so nothing is uploaded.
+    texture.with_region_mut([8, 0, 4, 4], |_| {});
+    draw(&tree, &mut texture_buffer);
+    assert_eq!(texture_buffer.factory.updated.len(), 1);
+
+    // Regions are clipped to the image.
+    texture.with_region_mut([6, 6, 4, 4], |image| image.put_pixel(7, 7, Rgba([255; 4])));
+    draw(&tree, &mut texture_buffer);
+    assert_eq!(texture_buffer.factory.updated[1], ([6, 6], [2, 2], 2 * 2 * 4));
+}
+
+#[test]
+fn edited_image_is_updated_whole() {
+    let texture = Texture::from(RgbaImage::new(8, 8));
+    let tree = record(&texture);
+    let mut texture_buffer = TextureBuffer::with_update(Factory::default());
+    draw(&tree, &mut texture_buffer);
+
+    texture.with_region_mut([2, 3, 1, 1], |image| image.put_pixel(2, 3, Rgba([255; 4])));
+    texture.with_image_mut(|image| image.put_pixel(0, 0, Rgba([255; 4])));
+    assert_eq!(texture.0.read().unwrap().dirty_rect, None);
+    draw(&tree, &mut texture_buffer);
+    assert_eq!(texture_buffer.factory.created, 1);
+    assert_eq!(texture_buffer.factory.updated, [([0, 0], [8, 8], 8 * 8 * 4)]);
+}
+
+#[test]
+fn resized_image_is_created_again() {
+    let texture = Texture::from(RgbaImage::new(8, 8));
+    let tree = record(&texture);
+    let mut texture_buffer = TextureBuffer::with_update(Factory::default());
+    draw(&tree, &mut texture_buffer);
+
+    texture.with_image_mut(|image| *image = RgbaImage::new(4, 4));
+    draw(&tree, &mut texture_buffer);
+    assert_eq!(texture_buffer.factory.created, 2);
+    assert!(texture_buffer.factory.updated.is_empty());
+}
+
+#[test]
+fn textures_are_created_again_without_update() {
+    let texture = Texture::from(RgbaImage::new(8, 8));
+    let tree = record(&texture);
+    let mut texture_buffer = TextureBuffer::new(Factory::default());
+    draw(&tree, &mut texture_buffer);
+
+    texture.with_image_mut(|image| image.put_pixel(0, 0, Rgba([255; 4])));
+    draw(&tree, &mut texture_buffer);
+    assert_eq!(texture_buffer.factory.created, 2);
+    assert!(texture_buffer.factory.updated.is_empty());
+}
+
+#[test]
+fn try_draw_returns_create_error() {
+    let texture = Texture::from(RgbaImage::new(8, 8));
+    let tree = record(&texture);
+    let mut texture_buffer = TextureBuffer::new(Factory {fail: true, ..Factory::default()});
+    let mut recorder = Recorder::default();
+    match tree.try_draw(&mut texture_buffer, &mut recorder) {
+        Err(DrawError::CreateTexture(())) => {}
+        res => panic!("unexpected result {:?}", res),
+    }
+    assert!(recorder.calls.is_empty());
+}
+
+#[test]
+fn try_draw_returns_missing_texture() {
+    let texture = Texture::from(RgbaImage::new(8, 8));
+    let tree = record(&texture);
+    draw(&tree, &mut TextureBuffer::new(Factory::default()));
+    let mut texture_buffer = TextureBuffer::new(Factory::default());
+    match tree.try_draw(&mut texture_buffer, &mut Recorder::default()) {
+        Err(DrawError::MissingTexture(0)) => {}
+        res => panic!("unexpected result {:?}", res),
+    }
+}
+
+#[test]
+fn try_draw_returns_poisoned() {
+    let texture = Texture::from(RgbaImage::new(8, 8));
+    let tree = record(&texture);
+    let poisoned = texture.clone();
+    let _ = panic::catch_unwind(move || poisoned.with_image_mut(|_| panic!("edit failed")));
+    let mut texture_buffer = TextureBuffer::new(Factory::default());
+    match tree.try_draw(&mut texture_buffer, &mut Recorder::default()) {
+        Err(DrawError::Poisoned) => {}
+        res => panic!("unexpected result {:?}", res),
+    }
+    // Reading the size and settings does not panic.
+    assert_eq!(texture.get_size(), (8, 8));
+    assert!(matches!(texture.settings().get_mag(), Filter::Linear));
+}
+
+#[test]
+#[should_panic(expected = "Could not create texture")]
+fn draw_panics_on_error() {
+    let texture = Texture::from(RgbaImage::new(8, 8));
+    let tree = record(&texture);
+    draw(&tree, &mut TextureBuffer::new(Factory {fail: true, ..Factory::default()}));
+}
+
+#[test]
+fn dropped_textures_are_collected() {
+    let mut texture_buffer = TextureBuffer::new(Factory::default());
+    let kept = Texture::from(RgbaImage::new(8, 8));
+    let tree = record(&kept);
+    {
+        let dropped = Texture::from(RgbaImage::new(8, 8));
+        draw(&record(&dropped), &mut texture_buffer);
+    }
+    draw(&tree, &mut texture_buffer);
+    assert_eq!(texture_buffer.len(), 1);
+    assert_eq!(texture_buffer.memory_usage(), 8 * 8 * 4);
+}
+
+#[test]
+fn unused_textures_are_swept() {
+    let mut texture_buffer = TextureBuffer::new(Factory::default());
+    let a = Texture::from(RgbaImage::new(8, 8));
+    let b = Texture::from(RgbaImage::new(8, 8));
+    draw(&record(&a), &mut texture_buffer);
+    draw(&record(&b), &mut texture_buffer);
+    assert_eq!(texture_buffer.sweep_unused(), 0);
+
+    draw(&record(&a), &mut texture_buffer);
+    assert_eq!(texture_buffer.sweep_unused(), 1);
+    assert_eq!(texture_buffer.len(), 1);
+
+    // The swept texture is created again when drawn.
+    draw(&record(&b), &mut texture_buffer);
+    assert_eq!(texture_buffer.factory.created, 3);
+}
+
+#[test]
+fn least_recently_used_textures_are_evicted() {
+    let mut texture_buffer = TextureBuffer::new(Factory::default());
+    texture_buffer.set_memory_budget(Some(2 * 8 * 8 * 4));
+    let textures: Vec<Texture> = (0..3).map(|_| Texture::from(RgbaImage::new(8, 8))).collect();
+    draw(&record(&textures[0]), &mut texture_buffer);
+    draw(&record(&textures[1]), &mut texture_buffer);
+    draw(&record(&textures[0]), &mut texture_buffer);
+    draw(&record(&textures[2]), &mut texture_buffer);
+    assert_eq!(texture_buffer.len(), 2);
+    assert!(texture_buffer.memory_usage() <= 2 * 8 * 8 * 4);
+    assert_eq!(textures[1].0.read().unwrap().id, None);
+    assert!(textures[0].0.read().unwrap().id.is_some());
+}
+
+#[test]
+fn textures_are_created_with_their_settings() {
+    let mut settings = TextureSettings::new();
+    settings.set_filter(Filter::Nearest);
+    let texture = Texture::with_settings(RgbaImage::new(8, 8), settings);
+    let tree = record(&texture);
+    let mut texture_buffer = TextureBuffer::with_update(Factory::default());
+    draw(&tree, &mut texture_buffer);
+    assert!(matches!(texture_buffer.factory.settings[0].get_mag(), Filter::Nearest));
+
+    settings.set_generate_mipmap(true);
+    texture.set_settings(settings);
+    draw(&tree, &mut texture_buffer);
+    assert_eq!(texture_buffer.factory.created, 2);
+    assert!(texture_buffer.factory.updated.is_empty());
+    assert!(texture_buffer.factory.settings[1].get_generate_mipmap());
+}
+
+#[test]
+fn small_textures_are_batched_in_atlas() {
+    let small: Vec<Texture> = (0..3).map(|_| Texture::from(RgbaImage::new(8, 8))).collect();
+    let large = Texture::from(RgbaImage::new(64, 64));
+    let mut tree = GraphicsTree::new();
+    for texture in small.iter().chain(Some(&large)) {
+        tree.tri_list_uv(&DrawState::default(), &WHITE, texture, |f| f(&TRIANGLE, &TRIANGLE));
+    }
+    let mut texture_buffer = TextureBuffer::new(Factory::default());
+    texture_buffer.enable_atlas(32, 16);
+    let mut recorder = Recorder::default();
+    tree.draw(&mut texture_buffer, &mut recorder);
+    assert_eq!(texture_buffer.factory.created, 2);
+    // The large texture is created while looking for draws to batch,
+    // before the page is uploaded.
+    assert_eq!(recorder.calls, [
+        Call::Draw(DrawState::default(), Some(WHITE), Some(2), vec![[TRIANGLE; 3].concat()]),
+        Call::Draw(DrawState::default(), Some(WHITE), Some(1), vec![TRIANGLE.to_vec()]),
+    ]);
+
+    // Changing a packed image uploads the page again.
+    small[1].with_image_mut(|image| image.put_pixel(0, 0, Rgba([255; 4])));
+    tree.draw(&mut texture_buffer, &mut Recorder::default());
+    assert_eq!(texture_buffer.factory.created, 3);
+}
+
+#[test]
+fn repeated_uvs_are_not_packed() {
+    let texture = Texture::from(RgbaImage::new(8, 8));
+    let repeated = [[0.0, 0.0], [2.0, 0.0], [0.0, 2.0]];
+    let mut tree = GraphicsTree::new();
+    tree.tri_list_uv(&DrawState::default(), &WHITE, &texture, |f| f(&TRIANGLE, &TRIANGLE));
+    tree.tri_list_uv(&DrawState::default(), &WHITE, &texture, |f| f(&TRIANGLE, &repeated));
+    let mut texture_buffer = TextureBuffer::new(Factory::default());
+    texture_buffer.enable_atlas(32, 16);
+    let mut recorder = Recorder::default();
+    tree.draw(&mut texture_buffer, &mut recorder);
+    // The texture for the repeated uvs is created while looking for draws to batch,
+    // before the page is uploaded.
+    assert_eq!(recorder.calls, [
+        Call::Draw(DrawState::default(), Some(WHITE), Some(2), vec![TRIANGLE.to_vec()]),
+        Call::Draw(DrawState::default(), Some(WHITE), Some(1), vec![TRIANGLE.to_vec()]),
+    ]);
+
+    // Both stay valid without being created again.
+    tree.draw(&mut texture_buffer, &mut Recorder::default());
+    assert_eq!(texture_buffer.factory.created, 2);
+}
+
+#[test]
+fn empty_pages_are_released() {
+    let small: Vec<Texture> = (0..2).map(|_| Texture::from(RgbaImage::new(8, 8))).collect();
+    let mut tree = GraphicsTree::new();
+    for texture in &small {
+        tree.tri_list_uv(&DrawState::default(), &WHITE, texture, |f| f(&TRIANGLE, &TRIANGLE));
+    }
+    let mut texture_buffer = TextureBuffer::new(Factory::default());
+    texture_buffer.enable_atlas(32, 16);
+    tree.draw(&mut texture_buffer, &mut Recorder::default());
+    assert_eq!(texture_buffer.memory_usage(), 32 * 32 * 4);
+
+    drop(tree);
+    drop(small);
+    assert_eq!(texture_buffer.collect_garbage(), 2);
+    assert_eq!(texture_buffer.memory_usage(), 0);
+
+    // The page is used again.
+    let texture = Texture::from(RgbaImage::new(8, 8));
+    let tree = record(&texture);
+    tree.draw(&mut texture_buffer, &mut Recorder::default());
+    assert_eq!(texture_buffer.factory.created, 2);
+    assert_eq!(texture_buffer.memory_usage(), 32 * 32 * 4);
+}
+
+#[test]
+fn unused_atlas_images_are_swept() {
+    let texture = Texture::from(RgbaImage::new(8, 8));
+    let tree = record(&texture);
+    let mut texture_buffer = TextureBuffer::new(Factory::default());
+    texture_buffer.enable_atlas(32, 16);
+    tree.draw(&mut texture_buffer, &mut Recorder::default());
+    assert_eq!(texture_buffer.sweep_unused(), 0);
+    assert_eq!(texture_buffer.sweep_unused(), 1);
+    assert_eq!(texture_buffer.memory_usage(), 0);
+
+    // The swept image is placed in the atlas again when drawn.
+    let mut recorder = Recorder::default();
+    tree.draw(&mut texture_buffer, &mut recorder);
+    assert_eq!(texture_buffer.factory.created, 2);
+    assert_eq!(texture_buffer.memory_usage(), 32 * 32 * 4);
+    assert_eq!(recorder.calls.len(), 1);
+}
+
+#[test]
+fn evicted_textures_release_their_atlas_images() {
+    let texture = Texture::from(RgbaImage::new(8, 8));
+    let repeated = [[0.0, 0.0], [2.0, 0.0], [0.0, 2.0]];
+    let mut tree = GraphicsTree::new();
+    tree.tri_list_uv(&DrawState::default(), &WHITE, &texture, |f| f(&TRIANGLE, &TRIANGLE));
+    tree.tri_list_uv(&DrawState::default(), &WHITE, &texture, |f| f(&TRIANGLE, &repeated));
+    let mut texture_buffer = TextureBuffer::new(Factory::default());
+    texture_buffer.enable_atlas(32, 16);
+    tree.draw(&mut texture_buffer, &mut Recorder::default());
+    assert_eq!(texture_buffer.memory_usage(), 32 * 32 * 4 + 8 * 8 * 4);
+
+    texture_buffer.set_memory_budget(Some(0));
+    assert!(texture_buffer.is_empty());
+    assert_eq!(texture_buffer.memory_usage(), 0);
+}
+
+#[test]
+fn moved_atlas_images_release_their_page() {
+    let texture = Texture::from(RgbaImage::new(8, 8));
+    let tree = record(&texture);
+    let mut texture_buffer = TextureBuffer::new(Factory::default());
+    texture_buffer.enable_atlas(32, 16);
+    draw(&tree, &mut texture_buffer);
+
+    // The image moves to a page with the new settings.
+    let mut settings = TextureSettings::new();
+    settings.set_filter(Filter::Nearest);
+    texture.set_settings(settings);
+    draw(&tree, &mut texture_buffer);
+    assert_eq!(texture_buffer.memory_usage(), 32 * 32 * 4);
+
+    drop(tree);
+    drop(texture);
+    texture_buffer.collect_garbage();
+    assert_eq!(texture_buffer.memory_usage(), 0);
+}
+
+#[test]
+fn batched_atlas_images_are_not_evicted_before_drawing() {
+    let small = Texture::from(RgbaImage::new(8, 8));
+    let large = Texture::from(RgbaImage::new(20, 20));
+    let repeated = [[0.0, 0.0], [2.0, 0.0], [0.0, 2.0]];
+    let mut texture_buffer = TextureBuffer::new(Factory::default());
+    texture_buffer.enable_atlas(32, 16);
+    texture_buffer.set_memory_budget(Some(4400));
+    // The small image gets both an atlas slot and a separate texture.
+    let mut tree = record(&small);
+    tree.tri_list_uv(&DrawState::default(), &WHITE, &small, |f| f(&TRIANGLE, &repeated));
+    draw(&tree, &mut texture_buffer);
+    assert_eq!(texture_buffer.memory_usage(), 32 * 32 * 4 + 8 * 8 * 4);
+
+    // Creating the large texture exceeds the budget,
+    // but the page is drawn before the small texture is evicted.
+    let mut tree = record(&small);
+    tree.tri_list_uv(&DrawState::default(), &WHITE, &large, |f| f(&TRIANGLE, &TRIANGLE));
+    let mut recorder = Recorder::default();
+    tree.draw(&mut texture_buffer, &mut recorder);
+    assert_eq!(recorder.calls, [
+        Call::Draw(DrawState::default(), Some(WHITE), Some(2), vec![TRIANGLE.to_vec()]),
+        Call::Draw(DrawState::default(), Some(WHITE), Some(3), vec![TRIANGLE.to_vec()]),
+    ]);
+    assert!(texture_buffer.memory_usage() <= 4400);
+}
+
+#[test]
+fn batches_are_drawn_before_errors_of_later_draws() {
+    let small = Texture::from(RgbaImage::new(8, 8));
+    let other = Texture::from(RgbaImage::new(8, 8));
+    let mut tree = record(&small);
+    tree.tri_list_uv(&DrawState::default(), &WHITE, &other, |f| f(&TRIANGLE, &TRIANGLE));
+    let mut texture_buffer = TextureBuffer::new(Factory::default());
+    texture_buffer.enable_atlas(32, 16);
+    // The other texture has an id of another texture buffer.
+    other.0.write().unwrap().id = Some(7);
+    let mut recorder = Recorder::default();
+    match tree.try_draw(&mut texture_buffer, &mut recorder) {
+        Err(DrawError::MissingTexture(7)) => {}
+        res => panic!("unexpected result {:?}", res),
+    }
+    assert_eq!(recorder.calls.len(), 1);
+}
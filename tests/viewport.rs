@@ -0,0 +1,56 @@
+extern crate graphics;
+extern crate graphics_tree;
+extern crate texture;
+
+mod common;
+
+use graphics::{DrawState, Graphics};
+use graphics_tree::GraphicsTree;
+use common::{draw_with_viewport, triangle};
+
+const RED: [f32; 4] = [1.0, 0.0, 0.0, 1.0];
+const BLUE: [f32; 4] = [0.0, 0.0, 1.0, 1.0];
+const VIEWPORT: [f32; 4] = [-1.0, -1.0, 2.0, 2.0];
+
+fn record() -> GraphicsTree {
+    let mut tree = GraphicsTree::new();
+    for i in 0..8 {
+        let color = if i % 2 == 0 {RED} else {BLUE};
+        tree.tri_list(&DrawState::default(), &color, |f| f(&triangle(i as f32 - 4.0)));
+    }
+    tree
+}
+
+#[test]
+fn draws_outside_viewport_are_skipped() {
+    let tree = record();
+    let vertices = draw_with_viewport(&tree, VIEWPORT).vertices();
+    // The last triangle touches the viewport.
+    assert_eq!(vertices, [triangle(-1.0), triangle(0.0), triangle(1.0)].concat());
+}
+
+#[test]
+fn children_are_culled_with_transform() {
+    let mut tree = GraphicsTree::new();
+    {
+        let node = tree.add_child("scroll");
+        node.tree = record();
+        node.transform = [[1.0, 0.0, 3.0], [0.0, 1.0, 0.0]];
+    }
+    let vertices = draw_with_viewport(&tree, VIEWPORT).vertices();
+    let moved = |x: f32| triangle(x).iter().map(|&[x, y]| [x + 3.0, y]).collect::<Vec<_>>();
+    assert_eq!(vertices, [moved(-4.0), moved(-3.0), moved(-2.0)].concat());
+}
+
+#[test]
+fn culling_uses_bounds_after_optimize() {
+    let mut tree = GraphicsTree::new();
+    for i in 0..8 {
+        tree.tri_list(&DrawState::default(), &RED, |f| f(&triangle(i as f32 - 4.0)));
+    }
+    tree.optimize();
+    assert_eq!(draw_with_viewport(&tree, VIEWPORT).vertices().len(), 8 * 3);
+
+    let tree = GraphicsTree::from_bytes(&record().to_bytes()).unwrap();
+    assert_eq!(draw_with_viewport(&tree, VIEWPORT).vertices().len(), 3 * 3);
+}